@@ -0,0 +1,94 @@
+//! times saving and loading a 200x100 map as json and in the compact format
+//!
+//! run with `cargo bench --bench file_format`
+
+use std::time::{Duration, Instant};
+
+use fakepaint::animation::Animation;
+use fakepaint::canvas::Layer;
+use fakepaint::file::{load_canvas_from_file, write_canvas_to_file, CanvasFileRef, Metadata};
+use fakepaint::tile::TileSetInfo;
+use fakepaint::{Canvas, Color, TileState};
+
+const ROUNDS: u32 = 10;
+
+/// a map with walls, floors and scattered items on a second layer
+fn sample_map() -> Animation {
+    let mut canvas = Canvas::with_size(200, 100);
+    canvas
+        .layers
+        .push(Layer::with_size("items".to_string(), 200 * 100));
+    let wall = TileState {
+        idx: 35,
+        fc: Color::from_rgb(180, 180, 180),
+        bc: Color::from_rgb(40, 40, 40),
+    };
+    let floor = TileState {
+        idx: 46,
+        fc: Color::from_rgb(90, 70, 50),
+        bc: Color::BLACK,
+    };
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let i = y * canvas.width + x;
+            let is_wall = x % 20 == 0 || y % 10 == 0;
+            canvas.layers[0].cells[i] = Some(if is_wall { wall } else { floor });
+            // a cheap hash so the items do not line up
+            let hash = (x * 7919 + y * 104729) % 97;
+            if !is_wall && hash < 3 {
+                canvas.layers[1].cells[i] = Some(TileState {
+                    idx: 33 + hash,
+                    fc: Color::from_rgb(255, (hash * 80) as u8, 0),
+                    bc: Color::BLACK,
+                });
+            }
+        }
+    }
+    Animation::from(canvas)
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let animation = sample_map();
+    let tileset = TileSetInfo::default();
+    let metadata = Metadata::default();
+    let document = CanvasFileRef {
+        tileset: &tileset,
+        palette: &[],
+        metadata: &metadata,
+        animation: &animation,
+    };
+    let dir = std::env::temp_dir();
+
+    let mut json_times = None;
+    println!(
+        "{:<6} {:>10} {:>10} {:>10} {:>14}",
+        "format", "size", "save", "load", "vs json"
+    );
+    for ext in ["json", "fpb", "fpz"] {
+        let path = dir.join(format!("fakepaint-bench.{}", ext));
+        let save = time(|| write_canvas_to_file(&document, &path).unwrap());
+        let load = time(|| {
+            load_canvas_from_file(&path).unwrap();
+        });
+        let size = std::fs::metadata(&path).unwrap().len();
+        let (json_save, json_load) = *json_times.get_or_insert((save, load));
+        println!(
+            "{:<6} {:>8.1}KB {:>8.2}ms {:>8.2}ms {:>5.1}x / {:>4.1}x",
+            ext,
+            size as f64 / 1024.0,
+            save.as_secs_f64() * 1000.0,
+            load.as_secs_f64() * 1000.0,
+            json_save.as_secs_f64() / save.as_secs_f64(),
+            json_load.as_secs_f64() / load.as_secs_f64(),
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
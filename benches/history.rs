@@ -0,0 +1,138 @@
+//! times painting, filling, undoing and redoing on a 500x500 canvas, against keeping a copy of
+//! the canvas for every undo step as a baseline
+//!
+//! run with `cargo bench --bench history`
+
+use std::time::{Duration, Instant};
+
+use fakepaint::tool::{Pen, ToolEnum};
+use fakepaint::undo::{Command, History};
+use fakepaint::Canvas;
+
+const SIZE: usize = 500;
+const STROKES: usize = 200;
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn report(name: &str, count: usize, elapsed: Duration) {
+    println!(
+        "{:<32} {:>8} {:>10.2}ms {:>10.2}µs each",
+        name,
+        count,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1_000_000.0 / count as f64
+    );
+}
+
+/// undo by snapshots, a copy of the whole canvas taken before every step
+#[derive(Default)]
+struct Snapshots {
+    undo: Vec<Canvas>,
+    redo: Vec<Canvas>,
+}
+
+impl Snapshots {
+    fn begin_step(&mut self, canvas: &Canvas) {
+        self.undo.push(canvas.clone());
+        self.redo.clear();
+    }
+
+    fn undo(&mut self, canvas: &mut Canvas) {
+        if let Some(prev) = self.undo.pop() {
+            self.redo.push(std::mem::replace(canvas, prev));
+        }
+    }
+
+    fn redo(&mut self, canvas: &mut Canvas) {
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(std::mem::replace(canvas, next));
+        }
+    }
+}
+
+/// a diagonal drag across the canvas, one cell per frame
+fn stroke(history: &mut History, canvas: &mut Canvas, pencil: &Pen, stroke: usize) {
+    history.begin_group();
+    for i in 0..SIZE {
+        let (x, y) = (i, (i + stroke) % SIZE);
+        let command = Command::new(x, y, pencil, canvas.get_cell(0, x, y), false, canvas);
+        history.push(canvas, command);
+    }
+    history.end_group();
+}
+
+fn main() {
+    let pencil = Pen::default();
+    let fill = Pen {
+        tool: ToolEnum::Fill,
+        idx: 1,
+        ..Default::default()
+    };
+
+    let mut canvas = Canvas::with_size(SIZE, SIZE);
+    let mut snapshots = Snapshots::default();
+    // the commands still have to be applied, a throwaway history does that for each step
+    let elapsed = time(|| {
+        for i in 0..STROKES {
+            snapshots.begin_step(&canvas);
+            stroke(&mut History::new(), &mut canvas, &pencil, i);
+        }
+    });
+    report("pencil stroke cells (snapshots)", STROKES * SIZE, elapsed);
+
+    let elapsed = time(|| {
+        snapshots.begin_step(&canvas);
+        let command = Command::new(SIZE - 1, 0, &fill, &None, false, &canvas);
+        History::new().push(&mut canvas, command);
+    });
+    report("fill (snapshots)", 1, elapsed);
+
+    let elapsed = time(|| {
+        for _ in 0..=STROKES {
+            snapshots.undo(&mut canvas);
+        }
+    });
+    report("undo (snapshots)", STROKES + 1, elapsed);
+
+    let elapsed = time(|| {
+        for _ in 0..=STROKES {
+            snapshots.redo(&mut canvas);
+        }
+    });
+    report("redo (snapshots)", STROKES + 1, elapsed);
+    drop(snapshots);
+
+    let mut canvas = Canvas::with_size(SIZE, SIZE);
+    let mut history = History::new();
+
+    let elapsed = time(|| {
+        for i in 0..STROKES {
+            stroke(&mut history, &mut canvas, &pencil, i);
+        }
+    });
+    report("pencil stroke cells", STROKES * SIZE, elapsed);
+
+    let elapsed = time(|| {
+        let command = Command::new(SIZE - 1, 0, &fill, &None, false, &canvas);
+        history.push(&mut canvas, command);
+    });
+    report("fill", 1, elapsed);
+
+    let elapsed = time(|| {
+        for _ in 0..=STROKES {
+            history.undo(&mut canvas);
+        }
+    });
+    report("undo", STROKES + 1, elapsed);
+
+    let elapsed = time(|| {
+        for _ in 0..=STROKES {
+            history.redo(&mut canvas);
+        }
+    });
+    report("redo", STROKES + 1, elapsed);
+}
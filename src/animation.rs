@@ -0,0 +1,379 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::Canvas;
+use crate::file::{with_path, FileError, FileErrorKind};
+use crate::tile::TileSet;
+
+/// the delay of a new frame in milliseconds
+pub const DEFAULT_FRAME_DELAY: u32 = 100;
+
+fn default_delay() -> u32 {
+    DEFAULT_FRAME_DELAY
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Frame {
+    /// how long the frame is shown in milliseconds
+    #[serde(default = "default_delay")]
+    pub delay: u32,
+    #[serde(flatten)]
+    pub canvas: Canvas,
+}
+
+impl Frame {
+    pub fn new(canvas: Canvas) -> Self {
+        Self {
+            delay: DEFAULT_FRAME_DELAY,
+            canvas,
+        }
+    }
+}
+
+/// a document made of frames, always holding at least one
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "AnimationRepr")]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+}
+
+// documents saved before animation existed are a single canvas
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnimationRepr {
+    Frames { frames: Vec<Frame> },
+    Single(Canvas),
+}
+
+impl From<AnimationRepr> for Animation {
+    fn from(value: AnimationRepr) -> Self {
+        match value {
+            AnimationRepr::Frames { frames } if !frames.is_empty() => Self { frames },
+            AnimationRepr::Frames { .. } => Self::from(Canvas::default()),
+            AnimationRepr::Single(canvas) => Self::from(canvas),
+        }
+    }
+}
+
+impl From<Canvas> for Animation {
+    fn from(canvas: Canvas) -> Self {
+        Self {
+            frames: vec![Frame::new(canvas)],
+        }
+    }
+}
+
+impl Animation {
+    pub fn duration(&self) -> u64 {
+        frame_duration(self.frames.iter().map(|frame| frame.delay))
+    }
+
+    /// the frame shown at `time` milliseconds into the looping animation
+    pub fn frame_at(&self, time: u64) -> usize {
+        frame_at(self.frames.iter().map(|frame| frame.delay), time)
+    }
+
+    /// renders every frame at the size of the largest one
+    fn render_frames(&self, tile: &TileSet, scale: u32) -> Vec<RgbaImage> {
+        let images: Vec<RgbaImage> = self
+            .frames
+            .iter()
+            .map(|frame| frame.canvas.render_image(tile, scale))
+            .collect();
+        let width = images.iter().map(|img| img.width()).max().unwrap_or(0);
+        let height = images.iter().map(|img| img.height()).max().unwrap_or(0);
+        images
+            .into_iter()
+            .map(|img| {
+                if img.dimensions() == (width, height) {
+                    img
+                } else {
+                    let mut padded = RgbaImage::new(width, height);
+                    image::imageops::replace(&mut padded, &img, 0, 0);
+                    padded
+                }
+            })
+            .collect()
+    }
+
+    /// `loop_count` 0 loops forever
+    pub fn export_as_gif(
+        &self,
+        tile: &TileSet,
+        path: &str,
+        scale: u32,
+        loop_count: u16,
+    ) -> Result<(), FileError> {
+        with_path(Path::new(path), || {
+            let writer = BufWriter::new(File::create(path)?);
+            self.write_gif(tile, scale, loop_count, writer)
+        })
+    }
+
+    pub fn write_gif(
+        &self,
+        tile: &TileSet,
+        scale: u32,
+        loop_count: u16,
+        writer: impl Write,
+    ) -> Result<(), FileErrorKind> {
+        let images = self.render_frames(tile, scale);
+        let too_large = |_| FileErrorKind::Invalid("image is too large for a gif".to_string());
+        let width = u16::try_from(images[0].width()).map_err(too_large)?;
+        let height = u16::try_from(images[0].height()).map_err(too_large)?;
+        let palette = GifPalette::new(&images);
+        let global_palette: Vec<u8> = palette
+            .as_ref()
+            .map(|palette| palette.colors.concat())
+            .unwrap_or_default();
+
+        let mut encoder = gif::Encoder::new(writer, width, height, &global_palette)?;
+        encoder.set_repeat(if loop_count == 0 {
+            gif::Repeat::Infinite
+        } else {
+            gif::Repeat::Finite(loop_count)
+        })?;
+        for (frame, img) in self.frames.iter().zip(images) {
+            let mut gif_frame = match &palette {
+                Some(palette) => gif::Frame {
+                    width,
+                    height,
+                    buffer: Cow::Owned(palette.indices(&img)),
+                    transparent: palette.transparent,
+                    ..Default::default()
+                },
+                // too many colours, quantize each frame on its own
+                None => gif::Frame::from_rgba_speed(width, height, &mut img.into_raw(), 10),
+            };
+            // gif delays are in hundredths of a second
+            gif_frame.delay = ((frame.delay + 5) / 10).clamp(1, u16::MAX as u32) as u16;
+            gif_frame.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&gif_frame)?;
+        }
+        Ok(())
+    }
+
+    /// `loop_count` 0 loops forever
+    pub fn export_as_apng(
+        &self,
+        tile: &TileSet,
+        path: &str,
+        scale: u32,
+        loop_count: u16,
+    ) -> Result<(), FileError> {
+        with_path(Path::new(path), || {
+            let writer = BufWriter::new(File::create(path)?);
+            self.write_apng(tile, scale, loop_count, writer)
+        })
+    }
+
+    pub fn write_apng(
+        &self,
+        tile: &TileSet,
+        scale: u32,
+        loop_count: u16,
+        writer: impl Write,
+    ) -> Result<(), FileErrorKind> {
+        let images = self.render_frames(tile, scale);
+        let mut encoder = png::Encoder::new(writer, images[0].width(), images[0].height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(images.len() as u32, loop_count as u32)?;
+        let mut writer = encoder.write_header()?;
+        for (frame, img) in self.frames.iter().zip(&images) {
+            // a delay of 0 plays as fast as the viewer can, playback waits at least 1ms
+            writer.set_frame_delay(frame.delay.clamp(1, u16::MAX as u32) as u16, 1000)?;
+            writer.write_image_data(img.as_raw())?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// a gif palette holding exactly the colours the frames use
+struct GifPalette {
+    colors: Vec<[u8; 3]>,
+    lookup: HashMap<[u8; 3], u8>,
+    transparent: Option<u8>,
+}
+
+impl GifPalette {
+    /// None when the frames use more colours than a gif palette holds
+    fn new(images: &[RgbaImage]) -> Option<Self> {
+        let mut palette = Self {
+            colors: Vec::new(),
+            lookup: HashMap::new(),
+            transparent: None,
+        };
+        for pixel in images.iter().flat_map(|img| img.pixels()) {
+            let [r, g, b, a] = pixel.0;
+            let known = if a < 128 {
+                palette.transparent.is_some()
+            } else {
+                palette.lookup.contains_key(&[r, g, b])
+            };
+            if known {
+                continue;
+            }
+            if palette.colors.len() == 256 {
+                return None;
+            }
+            let idx = palette.colors.len() as u8;
+            if a < 128 {
+                palette.transparent = Some(idx);
+                palette.colors.push([0, 0, 0]);
+            } else {
+                palette.lookup.insert([r, g, b], idx);
+                palette.colors.push([r, g, b]);
+            }
+        }
+        if palette.colors.is_empty() {
+            palette.colors.push([0, 0, 0]);
+        }
+        Some(palette)
+    }
+
+    fn indices(&self, img: &RgbaImage) -> Vec<u8> {
+        img.pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                if a < 128 {
+                    self.transparent.unwrap_or(0)
+                } else {
+                    self.lookup[&[r, g, b]]
+                }
+            })
+            .collect()
+    }
+}
+
+/// the length of one loop through frames with the given delays
+pub fn frame_duration(delays: impl Iterator<Item = u32>) -> u64 {
+    delays.map(|delay| delay.max(1) as u64).sum()
+}
+
+/// the index of the frame shown at `time` milliseconds into the loop of the given delays
+pub fn frame_at(delays: impl Iterator<Item = u32> + Clone, time: u64) -> usize {
+    let duration = frame_duration(delays.clone());
+    if duration == 0 {
+        return 0;
+    }
+    let mut time = time % duration;
+    let mut last = 0;
+    for (i, delay) in delays.enumerate() {
+        let delay = delay.max(1) as u64;
+        if time < delay {
+            return i;
+        }
+        time -= delay;
+        last = i;
+    }
+    last
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_at() {
+        let mut animation = Animation::from(Canvas::with_size(1, 1));
+        animation.frames.push(Frame {
+            delay: 50,
+            canvas: Canvas::with_size(1, 1),
+        });
+        assert_eq!(animation.duration(), 150);
+        assert_eq!(animation.frame_at(0), 0);
+        assert_eq!(animation.frame_at(99), 0);
+        assert_eq!(animation.frame_at(100), 1);
+        assert_eq!(animation.frame_at(160), 0);
+    }
+
+    #[test]
+    fn test_write_gif_and_apng() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::canvas::TileState;
+        use crate::color::Color;
+        use crate::tile::TileSetInfo;
+
+        let glyph = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+        let tile = TileSet::new(
+            image::DynamicImage::ImageRgba8(glyph),
+            TileSetInfo {
+                path: String::new(),
+                columns: 1,
+                rows: 1,
+                glyph_width: 2,
+                glyph_height: 2,
+            },
+        );
+        let mut animation = Animation::from(Canvas::with_size(2, 1));
+        let mut second = Frame::new(Canvas::with_size(2, 1));
+        second.delay = 250;
+        *second.canvas.get_cell_mut(0, 1, 0) = Some(TileState {
+            idx: 0,
+            fc: Color::from_rgb(255, 0, 0),
+            bc: Color::BLACK,
+        });
+        animation.frames.push(second);
+        let mut instant = Frame::new(Canvas::with_size(2, 1));
+        instant.delay = 0;
+        animation.frames.push(instant);
+
+        let mut gif_bytes = Vec::new();
+        animation.write_gif(&tile, 2, 3, &mut gif_bytes)?;
+        let mut decoder = gif::DecodeOptions::new().read_info(&gif_bytes[..])?;
+        // transparent and red
+        assert_eq!(decoder.global_palette().unwrap()[..6], [0, 0, 0, 255, 0, 0]);
+        assert_eq!((decoder.width(), decoder.height()), (8, 4));
+        let first = decoder.read_next_frame()?.unwrap();
+        assert_eq!(first.delay, 10);
+        assert_eq!(first.transparent, Some(0));
+        assert_eq!(decoder.read_next_frame()?.unwrap().delay, 25);
+        assert_eq!(decoder.read_next_frame()?.unwrap().delay, 1);
+        assert!(decoder.read_next_frame()?.is_none());
+
+        let mut png_bytes = Vec::new();
+        animation.write_apng(&tile, 1, 0, &mut png_bytes)?;
+        let mut reader = png::Decoder::new(&png_bytes[..]).read_info()?;
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 0));
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let mut delays = Vec::new();
+        for _ in 0..3 {
+            reader.next_frame(&mut buf)?;
+            let frame = reader.info().frame_control.unwrap();
+            delays.push((frame.delay_num, frame.delay_den));
+        }
+        assert_eq!(delays, [(100, 1000), (250, 1000), (1, 1000)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_de_single_canvas() -> Result<(), serde_json::error::Error> {
+        let animation: Animation =
+            serde_json::from_str(r#"{"width":2,"height":1,"cells":[null,null]}"#)?;
+        assert_eq!(animation.frames.len(), 1);
+        assert_eq!(animation.frames[0].delay, DEFAULT_FRAME_DELAY);
+
+        let json = serde_json::to_string(&Animation {
+            frames: vec![
+                Frame {
+                    delay: 40,
+                    canvas: Canvas::with_size(2, 1),
+                };
+                2
+            ],
+        })?;
+        let animation: Animation = serde_json::from_str(&json)?;
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(animation.frames[1].delay, 40);
+        assert_eq!(animation.frames[1].canvas.width, 2);
+        Ok(())
+    }
+}
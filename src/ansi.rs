@@ -0,0 +1,592 @@
+use std::io::Write;
+
+use crate::canvas::{Canvas, TileState, MAX_SIZE};
+use crate::color::Color;
+use crate::cp437;
+
+/// how the colors of the cells are written as SGR sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    TrueColor,
+    Xterm256,
+    Ansi16,
+}
+
+/// the usual vga colors of the 16 ansi colors
+pub const ANSI16_PALETTE: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [170, 0, 0],
+    [0, 170, 0],
+    [170, 85, 0],
+    [0, 0, 170],
+    [170, 0, 170],
+    [0, 170, 170],
+    [170, 170, 170],
+    [85, 85, 85],
+    [255, 85, 85],
+    [85, 255, 85],
+    [255, 255, 85],
+    [85, 85, 255],
+    [255, 85, 255],
+    [85, 255, 255],
+    [255, 255, 255],
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
+
+fn nearest_level(v: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+        .unwrap()
+}
+
+/// the nearest color of the xterm 256 color palette, skipping the first 16 colors
+pub fn rgb_to_xterm256(c: Color) -> u8 {
+    let rgb = [c.r(), c.g(), c.b()];
+    let (r, g, b) = (
+        nearest_level(rgb[0]),
+        nearest_level(rgb[1]),
+        nearest_level(rgb[2]),
+    );
+    let cube = [CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]];
+    let cube_idx = 16 + 36 * r + 6 * g + b;
+
+    let avg = (rgb[0] as u32 + rgb[1] as u32 + rgb[2] as u32) / 3;
+    let gray_step = (avg.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + 10 * gray_step;
+    let gray_idx = 232 + gray_step as usize;
+
+    if distance(rgb, [gray_level; 3]) < distance(rgb, cube) {
+        gray_idx as u8
+    } else {
+        cube_idx as u8
+    }
+}
+
+/// the index of the nearest of the 16 ansi colors
+pub fn rgb_to_ansi16(c: Color) -> u8 {
+    let rgb = [c.r(), c.g(), c.b()];
+    (0..ANSI16_PALETTE.len())
+        .min_by_key(|&i| distance(rgb, ANSI16_PALETTE[i]))
+        .unwrap() as u8
+}
+
+fn sgr(fc: Color, bc: Color, mode: ColorMode) -> String {
+    match mode {
+        ColorMode::TrueColor => format!(
+            "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+            fc.r(),
+            fc.g(),
+            fc.b(),
+            bc.r(),
+            bc.g(),
+            bc.b()
+        ),
+        ColorMode::Xterm256 => format!(
+            "\x1b[38;5;{};48;5;{}m",
+            rgb_to_xterm256(fc),
+            rgb_to_xterm256(bc)
+        ),
+        ColorMode::Ansi16 => {
+            let fc = rgb_to_ansi16(fc);
+            let bc = rgb_to_ansi16(bc);
+            let fc_code = if fc < 8 { 30 + fc } else { 90 + fc - 8 };
+            let bc_code = if bc < 8 { 40 + bc } else { 100 + bc - 8 };
+            format!("\x1b[{};{}m", fc_code, bc_code)
+        }
+    }
+}
+
+/// writes the visible layers of the canvas as ansi escape sequences, one line per row
+pub fn write_ansi<W: Write>(
+    canvas: &Canvas,
+    mode: ColorMode,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    for y in 0..canvas.height {
+        let mut last_colors = None;
+        for x in 0..canvas.width {
+            if let Some(tile) = canvas.composite_cell(x, y) {
+                let colors = sgr(tile.fc, tile.bc, mode);
+                if last_colors.as_ref() != Some(&colors) {
+                    writer.write_all(colors.as_bytes())?;
+                }
+                last_colors = Some(colors);
+                let c = cp437::idx_to_char(tile.idx).unwrap_or(' ');
+                write!(writer, "{}", c)?;
+            } else {
+                writer.write_all(b"\x1b[0m ")?;
+                last_colors = None;
+            }
+        }
+        writer.write_all(b"\x1b[0m\n")?;
+    }
+    Ok(())
+}
+
+/// the color of an xterm 256 color palette index
+pub fn xterm256_to_rgb(n: u8) -> Color {
+    let [r, g, b] = match n {
+        0..=15 => ANSI16_PALETTE[n as usize],
+        16..=231 => {
+            let n = n as usize - 16;
+            [
+                CUBE_LEVELS[n / 36],
+                CUBE_LEVELS[n / 6 % 6],
+                CUBE_LEVELS[n % 6],
+            ]
+        }
+        _ => [8 + 10 * (n - 232); 3],
+    };
+    Color::from_rgb(r, g, b)
+}
+
+/// the metadata record at the end of many .ans files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sauce {
+    pub width: u16,
+    pub height: u16,
+    pub ice_colors: bool,
+}
+
+const SAUCE_SIZE: usize = 128;
+const COMMENT_LINE_SIZE: usize = 64;
+
+/// splits the sauce record and its comments off the end of the file
+pub fn split_sauce(bytes: &[u8]) -> (&[u8], Option<Sauce>) {
+    if bytes.len() < SAUCE_SIZE {
+        return (bytes, None);
+    }
+    let start = bytes.len() - SAUCE_SIZE;
+    let record = &bytes[start..];
+    if &record[0..7] != b"SAUCE00" {
+        return (bytes, None);
+    }
+    let data_type = record[94];
+    let u16_at = |i: usize| u16::from_le_bytes([record[i], record[i + 1]]);
+    let sauce = Sauce {
+        // only character files keep the width and height in tinfo1 and tinfo2
+        width: if data_type == 1 { u16_at(96) } else { 0 },
+        height: if data_type == 1 { u16_at(98) } else { 0 },
+        ice_colors: record[105] & 1 == 1,
+    };
+    let comments = record[104] as usize;
+    let mut content_end = start;
+    let comment_size = 5 + comments * COMMENT_LINE_SIZE;
+    if comments > 0
+        && start >= comment_size
+        && &bytes[start - comment_size..start - comment_size + 5] == b"COMNT"
+    {
+        content_end -= comment_size;
+    }
+    (&bytes[..content_end], Some(sauce))
+}
+
+/// the canvas read from an ansi file and the sequences that could not be applied
+pub struct AnsiImport {
+    pub canvas: Canvas,
+    pub unsupported: Vec<String>,
+}
+
+const DEFAULT_ANSI_WIDTH: usize = 80;
+const DEFAULT_FC: AnsiColor = AnsiColor::Palette(7);
+const DEFAULT_BC: AnsiColor = AnsiColor::Palette(0);
+
+#[derive(Clone, Copy)]
+enum AnsiColor {
+    Palette(u8),
+    Rgb(Color),
+}
+
+struct AnsiParser {
+    width: usize,
+    ice_colors: bool,
+    rows: Vec<Vec<Option<TileState>>>,
+    max_x: usize,
+    x: usize,
+    y: usize,
+    saved: (usize, usize),
+    fc: AnsiColor,
+    bc: AnsiColor,
+    bold: bool,
+    blink: bool,
+    inverse: bool,
+    styled: bool,
+    unsupported: Vec<String>,
+}
+
+impl AnsiParser {
+    fn new(width: usize, ice_colors: bool) -> Self {
+        Self {
+            width,
+            ice_colors,
+            rows: Vec::new(),
+            max_x: 0,
+            x: 0,
+            y: 0,
+            saved: (0, 0),
+            fc: DEFAULT_FC,
+            bc: DEFAULT_BC,
+            bold: false,
+            blink: false,
+            inverse: false,
+            styled: false,
+            unsupported: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, what: String) {
+        if !self.unsupported.contains(&what) {
+            self.unsupported.push(what);
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.fc = DEFAULT_FC;
+        self.bc = DEFAULT_BC;
+        self.bold = false;
+        self.blink = false;
+        self.inverse = false;
+        self.styled = false;
+    }
+
+    fn colors(&self) -> (Color, Color) {
+        let resolve = |color: AnsiColor, bright: bool| match color {
+            AnsiColor::Palette(i) if bright && i < 8 => xterm256_to_rgb(i + 8),
+            AnsiColor::Palette(i) => xterm256_to_rgb(i),
+            AnsiColor::Rgb(c) => c,
+        };
+        let fc = resolve(self.fc, self.bold);
+        let bc = resolve(self.bc, self.blink && self.ice_colors);
+        if self.inverse {
+            (bc, fc)
+        } else {
+            (fc, bc)
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        let Some(idx) = cp437::char_to_idx(c) else {
+            self.report(format!("U+{:04X}", c as u32));
+            return;
+        };
+        if self.x >= self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+        if self.y >= MAX_SIZE {
+            return;
+        }
+        if self.rows.len() <= self.y {
+            self.rows.resize(self.y + 1, vec![None; self.width]);
+        }
+        self.rows[self.y][self.x] = if c == ' ' && !self.styled {
+            None
+        } else {
+            let (fc, bc) = self.colors();
+            Some(TileState { idx, fc, bc })
+        };
+        self.max_x = self.max_x.max(self.x);
+        self.x += 1;
+    }
+
+    fn extended_color(
+        &mut self,
+        params: &mut std::slice::Iter<Option<usize>>,
+    ) -> Option<AnsiColor> {
+        match params.next() {
+            Some(Some(5)) => match params.next() {
+                Some(Some(n)) if *n < 256 => Some(AnsiColor::Palette(*n as u8)),
+                _ => None,
+            },
+            Some(Some(2)) => {
+                let mut rgb = [0; 3];
+                for v in rgb.iter_mut() {
+                    match params.next() {
+                        Some(Some(n)) if *n < 256 => *v = *n as u8,
+                        _ => return None,
+                    }
+                }
+                Some(AnsiColor::Rgb(Color::from_rgb(rgb[0], rgb[1], rgb[2])))
+            }
+            _ => None,
+        }
+    }
+
+    fn sgr(&mut self, params: &[Option<usize>]) {
+        if params.is_empty() {
+            self.reset_attributes();
+            return;
+        }
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            let param = param.unwrap_or(0);
+            match param {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                5 | 6 if self.ice_colors => self.blink = true,
+                22 => self.bold = false,
+                25 => self.blink = false,
+                7 => self.inverse = true,
+                27 => self.inverse = false,
+                30..=37 => self.fc = AnsiColor::Palette((param - 30) as u8),
+                39 => self.fc = DEFAULT_FC,
+                40..=47 => self.bc = AnsiColor::Palette((param - 40) as u8),
+                49 => self.bc = DEFAULT_BC,
+                90..=97 => self.fc = AnsiColor::Palette((param - 90 + 8) as u8),
+                100..=107 => self.bc = AnsiColor::Palette((param - 100 + 8) as u8),
+                38 | 48 => match self.extended_color(&mut iter) {
+                    Some(color) if param == 38 => self.fc = color,
+                    Some(color) => self.bc = color,
+                    None => {
+                        self.report(format!("ESC[{}m", param));
+                        return;
+                    }
+                },
+                _ => {
+                    self.report(format!("ESC[{}m", param));
+                    continue;
+                }
+            }
+            if param != 0 {
+                self.styled = true;
+            }
+        }
+    }
+
+    fn csi(&mut self, private: bool, raw: &str, params: &[Option<usize>], final_char: char) {
+        let n = params.first().copied().flatten().unwrap_or(1).max(1);
+        match final_char {
+            _ if private => {
+                self.report(format!("ESC[{}{}", raw, final_char));
+            }
+            'm' => self.sgr(params),
+            'A' => self.y = self.y.saturating_sub(n),
+            // rows past the largest canvas are dropped, so moving further down does nothing
+            'B' => self.y = std::cmp::min(self.y.saturating_add(n), MAX_SIZE),
+            'C' => self.x = std::cmp::min(self.x.saturating_add(n), self.width - 1),
+            'D' => self.x = std::cmp::min(self.x, self.width).saturating_sub(n),
+            'H' | 'f' => {
+                let row = params.first().copied().flatten().unwrap_or(1).max(1);
+                let col = params.get(1).copied().flatten().unwrap_or(1).max(1);
+                self.y = std::cmp::min(row - 1, MAX_SIZE);
+                self.x = std::cmp::min(col - 1, self.width - 1);
+            }
+            's' => self.saved = (self.x, self.y),
+            'u' => (self.x, self.y) = self.saved,
+            'J' if params.first().copied().flatten() == Some(2) => {
+                self.rows.clear();
+                self.x = 0;
+                self.y = 0;
+            }
+            'K' if params.first().copied().flatten().unwrap_or(0) == 0 => {
+                if let Some(row) = self.rows.get_mut(self.y) {
+                    for cell in row.iter_mut().skip(self.x) {
+                        *cell = None;
+                    }
+                }
+            }
+            _ => self.report(format!("ESC[{}{}", raw, final_char)),
+        }
+    }
+
+    fn parse(&mut self, chars: &[char]) {
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            match c {
+                '\x1b' => {
+                    if chars.get(i) != Some(&'[') {
+                        let next = chars.get(i).map(|c| c.to_string()).unwrap_or_default();
+                        self.report(format!("ESC{}", next));
+                        i += 1;
+                        continue;
+                    }
+                    i += 1;
+                    let start = i;
+                    while i < chars.len() && !('\x40'..='\x7e').contains(&chars[i]) {
+                        i += 1;
+                    }
+                    let Some(&final_char) = chars.get(i) else {
+                        self.report("ESC[".to_string());
+                        break;
+                    };
+                    let raw: String = chars[start..i].iter().collect();
+                    i += 1;
+                    let private = raw.starts_with(['?', '<', '=', '>']);
+                    let params: Vec<Option<usize>> = if raw.is_empty() {
+                        Vec::new()
+                    } else {
+                        raw.trim_start_matches(['?', '<', '=', '>'])
+                            .split(';')
+                            .map(|p| p.parse().ok())
+                            .collect()
+                    };
+                    self.csi(private, &raw, &params, final_char);
+                }
+                '\r' => self.x = 0,
+                '\n' => {
+                    self.x = 0;
+                    self.y = std::cmp::min(self.y + 1, MAX_SIZE);
+                }
+                '\t' => self.x = std::cmp::min((self.x / 8 + 1) * 8, self.width),
+                _ => self.put(c),
+            }
+        }
+    }
+}
+
+/// reads cp437 or utf-8 ansi art, the text ends at the first SUB byte or the sauce record
+pub fn read_ansi(bytes: &[u8]) -> AnsiImport {
+    let (content, sauce) = split_sauce(bytes);
+    let content = match content.iter().position(|&b| b == 0x1a) {
+        Some(end) => &content[..end],
+        None => content,
+    };
+    let chars: Vec<char> = match std::str::from_utf8(content) {
+        Ok(text) if !text.is_ascii() => text.chars().collect(),
+        _ => content
+            .iter()
+            .map(|&b| match b {
+                0x1b | b'\r' | b'\n' | b'\t' | 0x20..=0x7e => b as char,
+                _ => cp437::idx_to_char(b as usize).unwrap_or(' '),
+            })
+            .collect(),
+    };
+
+    let sauce_width = sauce
+        .as_ref()
+        .map_or(0, |sauce| (sauce.width as usize).min(MAX_SIZE));
+    let width = if sauce_width > 0 {
+        sauce_width
+    } else {
+        DEFAULT_ANSI_WIDTH
+    };
+    let mut parser = AnsiParser::new(width, sauce.as_ref().is_some_and(|s| s.ice_colors));
+    parser.parse(&chars);
+
+    let canvas_width = if sauce_width > 0 {
+        sauce_width
+    } else {
+        parser.max_x + 1
+    };
+    let sauce_height = sauce
+        .as_ref()
+        .map_or(0, |sauce| (sauce.height as usize).min(MAX_SIZE));
+    let canvas_height = parser.rows.len().max(sauce_height).max(1);
+    let mut canvas = Canvas::with_size(canvas_width, canvas_height);
+    for (y, row) in parser.rows.iter().enumerate() {
+        for (x, cell) in row.iter().take(canvas_width).enumerate() {
+            *canvas.get_cell_mut(0, x, y) = *cell;
+        }
+    }
+    AnsiImport {
+        canvas,
+        unsupported: parser.unsupported,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_color_fallbacks() {
+        assert_eq!(rgb_to_xterm256(Color::from_rgb(255, 0, 0)), 196);
+        assert_eq!(rgb_to_xterm256(Color::from_rgb(128, 128, 128)), 244);
+        assert_eq!(rgb_to_xterm256(Color::BLACK), 16);
+        assert_eq!(rgb_to_ansi16(Color::from_rgb(250, 80, 90)), 9);
+        assert_eq!(rgb_to_ansi16(Color::from_rgb(0, 0, 160)), 4);
+    }
+
+    #[test]
+    fn test_write_ansi() {
+        let mut canvas = Canvas::with_size(3, 1);
+        let tile = Some(TileState {
+            idx: 1,
+            fc: Color::WHITE,
+            bc: Color::from_rgb(0, 0, 170),
+        });
+        *canvas.get_cell_mut(0, 0, 0) = tile;
+        *canvas.get_cell_mut(0, 1, 0) = tile;
+        let mut out = Vec::new();
+        write_ansi(&canvas, ColorMode::Ansi16, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[97;44m☺☺\x1b[0m \x1b[0m\n"
+        );
+        let mut out = Vec::new();
+        write_ansi(&canvas, ColorMode::TrueColor, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("\x1b[38;2;255;255;255;48;2;0;0;170m☺☺"));
+    }
+
+    fn sauce(width: u16, height: u16) -> Vec<u8> {
+        let mut record = vec![b' '; SAUCE_SIZE];
+        record[0..7].copy_from_slice(b"SAUCE00");
+        record[94] = 1;
+        record[96..98].copy_from_slice(&width.to_le_bytes());
+        record[98..100].copy_from_slice(&height.to_le_bytes());
+        record[104] = 0;
+        record[105] = 0;
+        record
+    }
+
+    #[test]
+    fn test_read_ansi() {
+        let mut bytes =
+            b"\x1b[2J\x1b[1;31;44mA\xdb\x1b[0m \x1b[3Cx\r\n\x1b[2;2H\x1b[4mB\x1b[?7h".to_vec();
+        bytes.push(0x1a);
+        bytes.extend(sauce(6, 3));
+        let import = read_ansi(&bytes);
+        let canvas = &import.canvas;
+        assert_eq!((canvas.width, canvas.height), (6, 3));
+        let a = canvas.get_cell(0, 0, 0).unwrap();
+        assert_eq!(a.idx, 'A' as usize);
+        assert_eq!(a.fc, xterm256_to_rgb(9));
+        assert_eq!(a.bc, xterm256_to_rgb(4));
+        assert_eq!(canvas.get_cell(0, 1, 0).unwrap().idx, 219);
+        assert_eq!(canvas.get_cell(0, 2, 0), &None);
+        assert_eq!(canvas.get_cell(0, 5, 0).unwrap().idx, 'x' as usize);
+        assert_eq!(canvas.get_cell(0, 1, 1).unwrap().idx, 'B' as usize);
+        assert_eq!(import.unsupported, vec!["ESC[4m", "ESC[?7h"]);
+    }
+
+    #[test]
+    fn test_read_ansi_far_cursor() {
+        let import = read_ansi(b"a\x1b[99999999Bb\x1b[18446744073709551615Cc\x1b[3000;1Hd\n\ne");
+        let canvas = import.canvas;
+        assert_eq!((canvas.width, canvas.height), (1, 1));
+        assert_eq!(canvas.get_cell(0, 0, 0).unwrap().idx, 'a' as usize);
+    }
+
+    #[test]
+    fn test_ansi_round_trip() {
+        let mut canvas = Canvas::with_size(4, 2);
+        *canvas.get_cell_mut(0, 0, 0) = Some(TileState {
+            idx: 2,
+            fc: Color::from_rgb(1, 2, 3),
+            bc: Color::from_rgb(200, 100, 0),
+        });
+        *canvas.get_cell_mut(0, 3, 1) = Some(TileState {
+            idx: 205,
+            fc: Color::WHITE,
+            bc: Color::BLACK,
+        });
+        let mut out = Vec::new();
+        write_ansi(&canvas, ColorMode::TrueColor, &mut out).unwrap();
+        let import = read_ansi(&out);
+        assert!(import.unsupported.is_empty());
+        assert_eq!(import.canvas.layers[0].cells, canvas.layers[0].cells);
+    }
+}
@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use fakepaint::animation::Animation;
+use fakepaint::ansi::ColorMode;
+use fakepaint::canvas::MAX_EXPORT_SCALE;
+use fakepaint::file::{
+    import_ansi_from_file, load_canvas_from_file, write_canvas_to_file, CanvasFile, CanvasFileRef,
+};
+use fakepaint::tile::TileSet;
+use fakepaint::validate;
+
+pub const USAGE: &str = "usage:
+    fakepaint export <input> <output.png|output.gif|output.apng|output.ans> [--scale N] [--tileset PATH]
+                     [--glyph-size WxH] [--grid COLUMNSxROWS] [--colors truecolor|256|16]
+    fakepaint convert <input> <output.json|output.fpz|output.fpb|output.xp>
+    fakepaint [document]";
+
+/// a subcommand that runs without opening a window
+#[derive(Debug, PartialEq)]
+pub enum CliCommand {
+    Help,
+    Export {
+        input: PathBuf,
+        output: PathBuf,
+        scale: u32,
+        tileset: Option<String>,
+        glyph_size: Option<(usize, usize)>,
+        grid: Option<(usize, usize)>,
+        colors: ColorMode,
+    },
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+    },
+}
+
+fn parse_pair(value: &str) -> Option<(usize, usize)> {
+    let (a, b) = value.split_once(['x', 'X'])?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+/// parses the arguments after the program name, None means the window should open, with the
+/// first argument as the document when there is one
+pub fn parse(args: &[String]) -> Option<Result<CliCommand, String>> {
+    let subcommand = args.first()?;
+    let command = match subcommand.as_str() {
+        "help" | "--help" | "-h" => Ok(CliCommand::Help),
+        "export" => parse_export(&args[1..]),
+        "convert" => match &args[1..] {
+            [input, output] => Ok(CliCommand::Convert {
+                input: input.into(),
+                output: output.into(),
+            }),
+            _ => Err("convert takes an input and an output file".to_string()),
+        },
+        other if other.starts_with('-') => Err(format!("unknown option `{}`", other)),
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn parse_export(args: &[String]) -> Result<CliCommand, String> {
+    let mut files = Vec::new();
+    let mut scale = 1;
+    let mut tileset = None;
+    let mut glyph_size = None;
+    let mut grid = None;
+    let mut colors = ColorMode::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            files.push(arg);
+            continue;
+        }
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--scale" => {
+                scale = value
+                    .parse()
+                    .ok()
+                    .filter(|scale| (1..=MAX_EXPORT_SCALE).contains(scale))
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid scale `{}`, it goes from 1 to {}",
+                            value, MAX_EXPORT_SCALE
+                        )
+                    })?
+            }
+            "--tileset" => tileset = Some(value.clone()),
+            "--glyph-size" => {
+                glyph_size =
+                    Some(parse_pair(value).ok_or_else(|| format!("invalid size `{}`", value))?)
+            }
+            "--grid" => {
+                grid = Some(parse_pair(value).ok_or_else(|| format!("invalid grid `{}`", value))?)
+            }
+            "--colors" => {
+                colors = match value.as_str() {
+                    "truecolor" => ColorMode::TrueColor,
+                    "256" => ColorMode::Xterm256,
+                    "16" => ColorMode::Ansi16,
+                    _ => return Err(format!("invalid colors `{}`", value)),
+                }
+            }
+            _ => return Err(format!("unknown option `{}`", arg)),
+        }
+    }
+    match files.as_slice() {
+        [input, output] => Ok(CliCommand::Export {
+            input: input.into(),
+            output: output.into(),
+            scale,
+            tileset,
+            glyph_size,
+            grid,
+            colors,
+        }),
+        _ => Err("export takes an input and an output file".to_string()),
+    }
+}
+
+fn load_input(path: &Path) -> Result<CanvasFile, Box<dyn Error>> {
+    if has_extension(path, "ans") {
+        let import = import_ansi_from_file(path)?;
+        for sequence in import.unsupported {
+            eprintln!("warning: unsupported sequence {}", sequence);
+        }
+        Ok(CanvasFile::from(Animation::from(import.canvas)))
+    } else {
+        Ok(load_canvas_from_file(path)?)
+    }
+}
+
+/// fixes a broken document, warning about every problem
+fn repair_input(path: &Path, animation: &mut Animation, glyph_count: usize) {
+    for problem in validate::validate(animation, glyph_count) {
+        eprintln!("warning: {}: {}, repaired", path.display(), problem);
+    }
+    validate::repair(animation, glyph_count);
+}
+
+/// a relative tileset path that is not in the current directory is looked up next to the executable
+fn find_tileset(path: &str) -> Result<String, String> {
+    if Path::new(path).exists() {
+        return Ok(path.to_string());
+    }
+    let next_to_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(path)));
+    match next_to_exe {
+        Some(found) if Path::new(path).is_relative() && found.exists() => {
+            Ok(found.to_string_lossy().into_owned())
+        }
+        _ => Err(format!(
+            "tileset `{}` not found, pass an existing one with --tileset",
+            path
+        )),
+    }
+}
+
+pub fn run(command: CliCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        CliCommand::Help => println!("{}", USAGE),
+        CliCommand::Export {
+            input,
+            output,
+            scale,
+            tileset: tileset_path,
+            glyph_size,
+            grid,
+            colors,
+        } => {
+            let CanvasFile {
+                mut animation,
+                tileset,
+                ..
+            } = load_input(&input)?;
+            let mut info = tileset.unwrap_or_default();
+            if let Some(path) = tileset_path {
+                info.path = path;
+            }
+            if let Some((width, height)) = glyph_size {
+                info.glyph_width = width;
+                info.glyph_height = height;
+            }
+            if let Some((columns, rows)) = grid {
+                info.columns = columns;
+                info.rows = rows;
+            }
+            repair_input(&input, &mut animation, info.glyph_count());
+            let canvas = &animation.frames[0].canvas;
+            let output_str = output.to_str().ok_or("output path is not valid utf-8")?;
+            if has_extension(&output, "ans") {
+                canvas.export_as_ansi(output_str, colors)?;
+            } else {
+                info.path = find_tileset(&info.path)?;
+                let tile = TileSet::load(&info)?;
+                if has_extension(&output, "gif") {
+                    animation.export_as_gif(&tile, output_str, scale, 0)?;
+                } else if has_extension(&output, "apng") {
+                    animation.export_as_apng(&tile, output_str, scale, 0)?;
+                } else {
+                    canvas.export_as_image(&tile, output_str, scale)?;
+                }
+            }
+        }
+        CliCommand::Convert { input, output } => {
+            let mut file = load_input(&input)?;
+            let info = file.tileset.unwrap_or_default();
+            repair_input(&input, &mut file.animation, info.glyph_count());
+            let document = CanvasFileRef {
+                tileset: &info,
+                palette: &file.palette,
+                metadata: &file.metadata,
+                animation: &file.animation,
+            };
+            write_canvas_to_file(&document, &output)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&[]), None);
+        assert_eq!(
+            parse(&args(
+                "export in.json out.png --scale 2 --tileset foo.png --grid 16x8"
+            )),
+            Some(Ok(CliCommand::Export {
+                input: "in.json".into(),
+                output: "out.png".into(),
+                scale: 2,
+                tileset: Some("foo.png".to_string()),
+                glyph_size: None,
+                grid: Some((16, 8)),
+                colors: ColorMode::TrueColor,
+            }))
+        );
+        assert_eq!(
+            parse(&args("convert in.xp out.json")),
+            Some(Ok(CliCommand::Convert {
+                input: "in.xp".into(),
+                output: "out.json".into(),
+            }))
+        );
+        assert!(parse(&args("export in.json --scale 0 out.png"))
+            .unwrap()
+            .is_err());
+        assert!(parse(&args("export in.json out.png --scale 200000"))
+            .unwrap()
+            .is_err());
+        assert!(parse(&args("--draw")).unwrap().is_err());
+        assert_eq!(parse(&args("drawing.json")), None);
+    }
+}
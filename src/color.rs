@@ -0,0 +1,68 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// an opaque rgb color, stored as `[r, g, b]` in files
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Color([u8; 3]);
+
+impl Color {
+    pub const WHITE: Color = Color([255, 255, 255]);
+    pub const BLACK: Color = Color([0, 0, 0]);
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Self([r, g, b])
+    }
+
+    pub fn r(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn g(&self) -> u8 {
+        self.0[1]
+    }
+
+    pub fn b(&self) -> u8 {
+        self.0[2]
+    }
+
+    pub fn to_array(&self) -> [u8; 3] {
+        self.0
+    }
+}
+
+impl From<[u8; 3]> for Color {
+    fn from(value: [u8; 3]) -> Self {
+        Self(value)
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deser: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Self(<[u8; 3]>::deserialize(deser)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_color_serde() -> Result<(), serde_json::error::Error> {
+        let color = Color::from_rgb(1, 2, 3);
+        assert_eq!(serde_json::to_string(&color)?, "[1,2,3]");
+        assert_eq!(serde_json::from_str::<Color>("[1,2,3]")?, color);
+        assert!(serde_json::from_str::<Color>("[1,2]").is_err());
+        Ok(())
+    }
+}
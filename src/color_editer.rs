@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 
-use crate::{canvas::TileState, file::load_palette, file::write_palette};
+use crate::color_ext::{ToColor, ToColor32};
 use eframe::egui;
+use fakepaint::file::{load_palette, write_palette};
+use fakepaint::shape::BoxDrawing;
+use fakepaint::tool::{Pen, ToolEnum};
 use palette::FromColor;
 
 use rust_i18n::t;
@@ -85,16 +88,10 @@ impl Default for StoragePen {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Copy)]
-pub enum ToolEnum {
-    Pencil,
-    Eraser,
-    Fill,
-    Replace,
-    RectFilled,
-}
 pub struct PencilState {
     pub tool: ToolEnum,
+    pub layer: usize,
+    pub box_drawing: BoxDrawing,
     pub start_xy: Option<(usize, usize)>,
     pub to_xy: Option<(usize, usize)>,
     pub idx: usize,
@@ -157,7 +154,8 @@ impl PencilState {
     pub fn palette_vec_ref(&self) -> &Vec<egui::Color32> {
         &self.palette.palette
     }
-    pub fn draw_palette(&mut self, ui: &mut egui::Ui) {
+    /// returns a message when saving or loading the palette failed
+    pub fn draw_palette(&mut self, ui: &mut egui::Ui) -> Option<String> {
         use crate::{TILE_SIZE, TILE_SIZE_VEC2};
         use egui::containers::scroll_area::ScrollBarVisibility;
         const SCALE_FACT: f32 = 1.25;
@@ -239,6 +237,7 @@ impl PencilState {
             res
         }
 
+        let mut error = None;
         ui.vertical(|ui| {
             ui.heading(t!("palette"));
             ui.horizontal(|ui| {
@@ -249,7 +248,14 @@ impl PencilState {
                         .add_filter("json", &["json"])
                         .save_file()
                     {
-                        let _ = write_palette(self.palette_vec_ref(), &path);
+                        let palette: Vec<_> = self
+                            .palette_vec_ref()
+                            .iter()
+                            .map(|c| c.to_color())
+                            .collect();
+                        if let Err(e) = write_palette(&palette, &path) {
+                            error = Some(format!("{}: {}", t!("save_failed"), e));
+                        }
                     }
                 }
 
@@ -259,8 +265,15 @@ impl PencilState {
                         .add_filter("json", &["json"])
                         .pick_file()
                     {
-                        if let Ok(pp) = load_palette(std::path::Path::new(&path)) {
-                            self.palette = pp.into();
+                        match load_palette(std::path::Path::new(&path)) {
+                            Ok(pp) => {
+                                self.palette = pp
+                                    .into_iter()
+                                    .map(|c| c.to_color32())
+                                    .collect::<Vec<_>>()
+                                    .into();
+                            }
+                            Err(e) => error = Some(format!("{}: {}", t!("load_failed"), e)),
                         }
                     }
                 }
@@ -329,30 +342,26 @@ impl PencilState {
                         });
                 });
         });
+        error
     }
     pub fn swap_fc_bc(&mut self) {
         std::mem::swap(&mut self.fc, &mut self.bc);
         self.state = ColorEditerState::RGB;
     }
 
-    pub fn get_fc_bc(&self, cell: &Option<TileState>) -> Option<(egui::Color32, egui::Color32)> {
-        if (!self.fc_activate && !self.bc_activate) || self.tool == ToolEnum::Eraser {
-            None
-        } else if *cell == None {
-            Some((self.fc, self.bc))
-        } else {
-            Some((
-                if self.fc_activate {
-                    self.fc
-                } else {
-                    cell.unwrap().fc
-                },
-                if self.bc_activate {
-                    self.bc
-                } else {
-                    cell.unwrap().bc
-                },
-            ))
+    /// a snapshot of the tool, glyph and colors for building commands
+    pub fn pen(&self) -> Pen {
+        Pen {
+            tool: self.tool,
+            layer: self.layer,
+            box_drawing: self.box_drawing,
+            start_xy: self.start_xy,
+            to_xy: self.to_xy,
+            idx: self.idx,
+            fc: self.fc.to_color(),
+            bc: self.bc.to_color(),
+            fc_activate: self.fc_activate,
+            bc_activate: self.bc_activate,
         }
     }
 
@@ -389,18 +398,6 @@ impl PencilState {
         self.palette.palette.len()
     }
 
-    pub fn into_tile_state(&self, origin_state: &Option<TileState>) -> Option<TileState> {
-        if let Some((fc, bc)) = self.get_fc_bc(origin_state) {
-            Some(TileState {
-                idx: self.idx,
-                fc,
-                bc,
-            })
-        } else {
-            None
-        }
-    }
-
     pub fn fore_color_checkbox(&mut self, ui: &mut egui::Ui) -> egui::Response {
         ui.checkbox(
             &mut self.fc_activate,
@@ -415,14 +412,6 @@ impl PencilState {
         )
     }
 
-    pub fn swap_color_and_into(&self) -> Option<TileState> {
-        Some(TileState {
-            idx: self.idx,
-            fc: self.bc,
-            bc: self.fc,
-        })
-    }
-
     pub fn color_editer(&mut self, ui: &mut egui::Ui) -> egui::Response {
         let (color, other_color) = if self.editing == EditingColor::FORE {
             (&mut self.fc, self.bc)
@@ -566,6 +555,8 @@ impl Default for PencilState {
     fn default() -> Self {
         Self {
             tool: ToolEnum::Pencil,
+            layer: 0,
+            box_drawing: BoxDrawing::Off,
             start_xy: None,
             to_xy: None,
             idx: 0,
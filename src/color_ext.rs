@@ -0,0 +1,25 @@
+use eframe::egui::Color32;
+use fakepaint::color::Color;
+
+/// converts document colors for drawing with egui
+pub trait ToColor32 {
+    fn to_color32(self) -> Color32;
+}
+
+impl ToColor32 for Color {
+    fn to_color32(self) -> Color32 {
+        Color32::from_rgb(self.r(), self.g(), self.b())
+    }
+}
+
+/// converts colors picked in egui widgets into document colors
+pub trait ToColor {
+    fn to_color(self) -> Color;
+}
+
+impl ToColor for Color32 {
+    fn to_color(self) -> Color {
+        let c = self.to_opaque();
+        Color::from_rgb(c.r(), c.g(), c.b())
+    }
+}
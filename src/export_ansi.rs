@@ -0,0 +1,85 @@
+use eframe::egui;
+
+use fakepaint::ansi::ColorMode;
+use fakepaint::file::FileError;
+
+pub struct ExportAnsiWindow {
+    open: bool,
+    pub mode: ColorMode,
+    pub file_name: String,
+}
+
+impl ExportAnsiWindow {
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// returns why exporting failed
+    pub fn show(&mut self, ctx: &egui::Context, canvas: &crate::Canvas) -> Option<FileError> {
+        use rust_i18n::t;
+        let mut created = false;
+        let mut error = None;
+        egui::Window::new(t!("export_ansi"))
+            .resizable(false)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                egui::Grid::new("export-ansi")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(t!("file_name"));
+                        ui.text_edit_singleline(&mut self.file_name);
+                        if ui.button(t!("browse")).clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("ans", &["ans"])
+                                .save_file()
+                            {
+                                if let Some(string) = path.to_str() {
+                                    self.file_name = string.to_string();
+                                }
+                            }
+                        }
+                        ui.end_row();
+                        ui.label(t!("color_mode"));
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut self.mode,
+                                ColorMode::TrueColor,
+                                t!("true_color"),
+                            );
+                            ui.selectable_value(
+                                &mut self.mode,
+                                ColorMode::Xterm256,
+                                t!("xterm_256"),
+                            );
+                            ui.selectable_value(&mut self.mode, ColorMode::Ansi16, t!("ansi_16"));
+                        });
+                        ui.end_row();
+                        if ui.button(t!("export")).clicked() && !self.file_name.is_empty() {
+                            let mut path = std::path::PathBuf::new();
+                            path.push(&self.file_name);
+                            path.set_extension("ans");
+                            if let Some(string) = path.to_str() {
+                                let result = canvas.export_as_ansi(string, self.mode);
+                                created = result.is_ok();
+                                error = result.err();
+                            }
+                        }
+                    });
+            });
+        if created {
+            self.open = false;
+        }
+        error
+    }
+}
+
+impl Default for ExportAnsiWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            mode: ColorMode::default(),
+            file_name: "".to_string(),
+        }
+    }
+}
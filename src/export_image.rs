@@ -1,9 +1,34 @@
 use eframe::egui;
 
+use fakepaint::animation::Animation;
+use fakepaint::canvas::MAX_EXPORT_SCALE;
+use fakepaint::file::FileError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExportFormat {
+    /// the edited frame as a still image
+    #[default]
+    Png,
+    Gif,
+    Apng,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png | ExportFormat::Apng => "png",
+            ExportFormat::Gif => "gif",
+        }
+    }
+}
+
 pub struct ExportImageWindow {
     open: bool,
     pub scale: u32,
     pub file_name: String,
+    pub format: ExportFormat,
+    /// how many times animations play, 0 for forever
+    pub loop_count: u16,
 }
 
 impl ExportImageWindow {
@@ -11,9 +36,17 @@ impl ExportImageWindow {
         self.open = true;
     }
 
-    pub fn show(&mut self, ctx: &egui::Context, canvas: &crate::Canvas, tile: &crate::TileSet) {
+    /// `animation` is only called when an animation is exported, returns why exporting failed
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        canvas: &crate::Canvas,
+        tile: &crate::TileSet,
+        animation: impl FnOnce() -> Animation,
+    ) -> Option<FileError> {
         use rust_i18n::t;
         let mut created = false;
+        let mut error = None;
         egui::Window::new(t!("export_image"))
             .resizable(false)
             .open(&mut self.open)
@@ -25,8 +58,9 @@ impl ExportImageWindow {
                         ui.label(t!("file_name"));
                         ui.text_edit_singleline(&mut self.file_name);
                         if ui.button(t!("browse")).clicked() {
+                            let extension = self.format.extension();
                             if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("png", &["png"])
+                                .add_filter(extension, &[extension])
                                 .save_file()
                             {
                                 if let Some(string) = path.to_str() {
@@ -37,17 +71,46 @@ impl ExportImageWindow {
                         ui.end_row();
                         ui.label(t!("scale"));
                         ui.add(
-                            egui::DragValue::new(&mut self.scale)
-                                .clamp_range(core::ops::RangeInclusive::new(1, 16)),
+                            egui::DragValue::new(&mut self.scale).clamp_range(1..=MAX_EXPORT_SCALE),
                         );
                         ui.end_row();
+                        ui.label(t!("image_format"));
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut self.format, ExportFormat::Png, "PNG");
+                            ui.selectable_value(&mut self.format, ExportFormat::Gif, "GIF");
+                            ui.selectable_value(&mut self.format, ExportFormat::Apng, "APNG");
+                        });
+                        ui.end_row();
+                        if self.format != ExportFormat::Png {
+                            ui.label(t!("loop_count"));
+                            ui.add(egui::DragValue::new(&mut self.loop_count))
+                                .on_hover_text(t!("loop_forever"));
+                            ui.end_row();
+                        }
                         if ui.button(t!("export")).clicked() && self.file_name.len() > 0 {
                             let mut path = std::path::PathBuf::new();
                             path.push(&self.file_name);
-                            path.set_extension("png");
+                            path.set_extension(self.format.extension());
                             if let Some(string) = path.to_str() {
-                                canvas.export_as_image(tile, string, self.scale);
-                                created = true;
+                                let result = match self.format {
+                                    ExportFormat::Png => {
+                                        canvas.export_as_image(tile, string, self.scale)
+                                    }
+                                    ExportFormat::Gif => animation().export_as_gif(
+                                        tile,
+                                        string,
+                                        self.scale,
+                                        self.loop_count,
+                                    ),
+                                    ExportFormat::Apng => animation().export_as_apng(
+                                        tile,
+                                        string,
+                                        self.scale,
+                                        self.loop_count,
+                                    ),
+                                };
+                                created = result.is_ok();
+                                error = result.err();
                             }
                         }
                     });
@@ -55,6 +118,7 @@ impl ExportImageWindow {
         if created {
             self.open = false;
         }
+        error
     }
 }
 
@@ -64,6 +128,8 @@ impl Default for ExportImageWindow {
             open: false,
             file_name: "".to_string(),
             scale: 1,
+            format: ExportFormat::default(),
+            loop_count: 0,
         }
     }
 }
@@ -1,54 +1,902 @@
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
-use crate::canvas::Canvas;
+use crate::animation::{Animation, Frame};
+use crate::ansi::{self, AnsiImport};
+use crate::canvas::{default_layer_name, Canvas, Layer, TileState};
+use crate::color::Color;
+use crate::tile::TileSetInfo;
+use crate::validate;
 
-use serde::Serialize;
-pub fn load_canvas_from_file(path: &Path) -> Result<Canvas, Box<dyn Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let u: Canvas = serde_json::from_reader(reader)?;
-    Ok(u)
+use serde::{Deserialize, Serialize};
+
+/// why reading or writing a file failed
+#[derive(Debug)]
+pub enum FileErrorKind {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Image(image::ImageError),
+    /// gif or png encoding failed
+    Encode(String),
+    /// the file was read but does not hold what it should
+    Invalid(String),
+    /// the file declares sizes no canvas can have, it is damaged or crafted
+    Corrupt(String),
+}
+
+impl fmt::Display for FileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileErrorKind::Io(e) => e.fmt(f),
+            FileErrorKind::Json(e) => e.fmt(f),
+            FileErrorKind::Image(e) => e.fmt(f),
+            FileErrorKind::Encode(message)
+            | FileErrorKind::Invalid(message)
+            | FileErrorKind::Corrupt(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for FileErrorKind {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FileErrorKind::Io(e) => Some(e),
+            FileErrorKind::Json(e) => Some(e),
+            FileErrorKind::Image(e) => Some(e),
+            FileErrorKind::Encode(_) | FileErrorKind::Invalid(_) | FileErrorKind::Corrupt(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for FileErrorKind {
+    fn from(e: std::io::Error) -> Self {
+        FileErrorKind::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for FileErrorKind {
+    fn from(e: serde_json::Error) -> Self {
+        FileErrorKind::Json(e)
+    }
+}
+
+impl From<image::ImageError> for FileErrorKind {
+    fn from(e: image::ImageError) -> Self {
+        FileErrorKind::Image(e)
+    }
+}
+
+impl From<gif::EncodingError> for FileErrorKind {
+    fn from(e: gif::EncodingError) -> Self {
+        match e {
+            gif::EncodingError::Io(e) => FileErrorKind::Io(e),
+            e => FileErrorKind::Encode(e.to_string()),
+        }
+    }
+}
+
+impl From<png::EncodingError> for FileErrorKind {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => FileErrorKind::Io(e),
+            e => FileErrorKind::Encode(e.to_string()),
+        }
+    }
+}
+
+/// a failed file operation and the file it was done on
+#[derive(Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub kind: FileErrorKind,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+/// runs `f` and tags its error with `path`
+pub(crate) fn with_path<T>(
+    path: &Path,
+    f: impl FnOnce() -> Result<T, FileErrorKind>,
+) -> Result<T, FileError> {
+    f().map_err(|kind| FileError {
+        path: path.to_path_buf(),
+        kind,
+    })
+}
+
+/// the version of the document format written by this build
+///
+/// 1 is everything before versioning, a bare canvas or frames next to an optional tileset
+pub const FORMAT_VERSION: u64 = 2;
+
+/// free-form information about a document
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub author: String,
+    /// any other fields, kept as they were written
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// a document on disk together with the tileset it was drawn with
+#[derive(Deserialize)]
+pub struct CanvasFile {
+    #[serde(default)]
+    pub tileset: Option<TileSetInfo>,
+    /// empty when the document brings no palette
+    #[serde(default)]
+    pub palette: Vec<Color>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(flatten)]
+    pub animation: Animation,
+}
+
+impl From<Animation> for CanvasFile {
+    fn from(animation: Animation) -> Self {
+        Self {
+            tileset: None,
+            palette: Vec::new(),
+            metadata: Metadata::default(),
+            animation,
+        }
+    }
+}
+
+/// a document to be written, borrowed from wherever it is edited
+#[derive(Serialize)]
+pub struct CanvasFileRef<'a> {
+    pub tileset: &'a TileSetInfo,
+    pub palette: &'a [Color],
+    pub metadata: &'a Metadata,
+    #[serde(flatten)]
+    pub animation: &'a Animation,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    #[serde(flatten)]
+    document: &'a CanvasFileRef<'a>,
+}
+
+/// brings a document written by an older version up to [`FORMAT_VERSION`], one version at a time
+fn migrate(mut value: serde_json::Value) -> Result<serde_json::Value, FileErrorKind> {
+    let Some(object) = value.as_object_mut() else {
+        return Err(FileErrorKind::Invalid(
+            "not a fakepaint document".to_string(),
+        ));
+    };
+    let version = object
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or(1);
+    if version > FORMAT_VERSION {
+        return Err(FileErrorKind::Invalid(format!(
+            "written by a newer fakepaint, format version {} is not supported",
+            version
+        )));
+    }
+    if version < 2 {
+        migrate_v1(object);
+    }
+    Ok(value)
+}
+
+/// moves a bare canvas into a single frame
+fn migrate_v1(object: &mut serde_json::Map<String, serde_json::Value>) {
+    if !object.contains_key("frames") {
+        let mut frame = serde_json::Map::new();
+        for key in ["width", "height", "layers", "cells"] {
+            if let Some(value) = object.remove(key) {
+                frame.insert(key.to_string(), value);
+            }
+        }
+        object.insert("frames".to_string(), vec![frame].into());
+    }
+    object.insert("version".to_string(), 2.into());
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+/// whether writing to `path` drops part of the document, xp files only hold the cells of the first frame
+pub fn is_lossy_format(path: &Path) -> bool {
+    has_extension(path, "xp")
+}
+
+pub fn load_canvas_from_file(path: &Path) -> Result<CanvasFile, FileError> {
+    with_path(path, || {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        if has_extension(path, "xp") {
+            return Ok(CanvasFile::from(Animation::from(read_xp(reader)?)));
+        }
+        if has_extension(path, "fpb") || has_extension(path, "fpz") {
+            return read_fpb(reader);
+        }
+        let value = migrate(serde_json::from_reader(reader)?)?;
+        let u: CanvasFile = serde_json::from_value(value)?;
+        Ok(u)
+    })
+}
+
+pub fn import_ansi_from_file(path: &Path) -> Result<AnsiImport, FileError> {
+    with_path(path, || {
+        let bytes = std::fs::read(path)?;
+        Ok(ansi::read_ansi(&bytes))
+    })
+}
+
+/// the format is picked by extension: `.fpb` and `.fpz` are the compact format, the latter
+/// gzip compressed, xp files have no frames or metadata so only the first frame is written to them,
+/// anything else is json
+pub fn write_canvas_to_file(u: &CanvasFileRef, path: &Path) -> Result<(), FileError> {
+    with_path(path, || {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        if has_extension(path, "xp") {
+            return write_xp(&u.animation.frames[0].canvas, writer);
+        }
+        if has_extension(path, "fpb") {
+            return write_fpb(u, writer, false);
+        }
+        if has_extension(path, "fpz") {
+            return write_fpb(u, writer, true);
+        }
+        serde_json::to_writer_pretty(
+            writer,
+            &Envelope {
+                version: FORMAT_VERSION,
+                document: u,
+            },
+        )?;
+        Ok(())
+    })
+}
+
+// REXPaint marks transparent cells with a magenta background
+const XP_TRANSPARENT: [u8; 3] = [255, 0, 255];
+
+fn read_i32(reader: &mut impl Read) -> std::io::Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
 }
 
-pub fn write_canvas_to_file(u: &impl Serialize, path: &Path) -> Result<(), Box<dyn Error>> {
-    let file = File::create(path)?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, u)?;
+fn read_rgb(reader: &mut impl Read) -> std::io::Result<[u8; 3]> {
+    let mut buf = [0; 3];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn too_large(width: usize, height: usize) -> FileErrorKind {
+    FileErrorKind::Corrupt(format!("canvas size {}x{} is too large", width, height))
+}
+
+/// reads a gzip compressed REXPaint image, layers are stored column-major
+pub fn read_xp(reader: impl Read) -> Result<Canvas, FileErrorKind> {
+    let mut reader = flate2::read::GzDecoder::new(reader);
+    let mut layer_count = read_i32(&mut reader)?;
+    // files written by REXPaint 1.02+ start with a negative version number
+    if layer_count < 0 {
+        layer_count = read_i32(&mut reader)?;
+    }
+    if layer_count <= 0 || layer_count as usize > crate::canvas::MAX_LAYERS {
+        return Err(FileErrorKind::Invalid(format!(
+            "invalid xp layer count: {}",
+            layer_count
+        )));
+    }
+
+    let mut canvas: Option<Canvas> = None;
+    for i in 0..layer_count as usize {
+        let width = read_i32(&mut reader)?;
+        let height = read_i32(&mut reader)?;
+        if width <= 0 || height <= 0 {
+            return Err(FileErrorKind::Invalid(format!(
+                "invalid xp layer size: {}x{}",
+                width, height
+            )));
+        }
+        let (width, height) = (width as usize, height as usize);
+        if validate::canvas_size(width, height).is_none() {
+            return Err(too_large(width, height));
+        }
+        let canvas = canvas.get_or_insert_with(|| {
+            let mut canvas = Canvas::with_size(width, height);
+            canvas.layers.clear();
+            canvas
+        });
+        let mut layer = Layer::with_size(default_layer_name(i), canvas.width * canvas.height);
+        for x in 0..width {
+            for y in 0..height {
+                let idx = read_i32(&mut reader)? as u32 as usize;
+                let fc = read_rgb(&mut reader)?;
+                let bc = read_rgb(&mut reader)?;
+                if bc == XP_TRANSPARENT || x >= canvas.width || y >= canvas.height {
+                    continue;
+                }
+                layer.cells[y * canvas.width + x] = Some(TileState {
+                    idx,
+                    fc: Color::from_rgb(fc[0], fc[1], fc[2]),
+                    bc: Color::from_rgb(bc[0], bc[1], bc[2]),
+                });
+            }
+        }
+        canvas.layers.push(layer);
+    }
+    Ok(canvas.unwrap())
+}
+
+/// writes the canvas as a gzip compressed REXPaint image
+pub fn write_xp(canvas: &Canvas, writer: impl Write) -> Result<(), FileErrorKind> {
+    const XP_VERSION: i32 = -1;
+    let mut writer = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+    writer.write_all(&XP_VERSION.to_le_bytes())?;
+    writer.write_all(&(canvas.layers.len() as i32).to_le_bytes())?;
+    for layer in 0..canvas.layers.len() {
+        writer.write_all(&(canvas.width as i32).to_le_bytes())?;
+        writer.write_all(&(canvas.height as i32).to_le_bytes())?;
+        for x in 0..canvas.width {
+            for y in 0..canvas.height {
+                if let &Some(tile) = canvas.get_cell(layer, x, y) {
+                    let [fr, fg, fb] = tile.fc.to_array();
+                    let [br, bg, bb] = tile.bc.to_array();
+                    writer.write_all(&(tile.idx as i32).to_le_bytes())?;
+                    writer.write_all(&[fr, fg, fb, br, bg, bb])?;
+                } else {
+                    writer.write_all(&0i32.to_le_bytes())?;
+                    writer.write_all(&[0, 0, 0])?;
+                    writer.write_all(&XP_TRANSPARENT)?;
+                }
+            }
+        }
+    }
+    writer.finish()?.flush()?;
     Ok(())
 }
 
-use eframe::egui;
+// compact documents start with this, possibly inside a gzip stream
+const FPB_MAGIC: &[u8; 4] = b"FPB\0";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// everything of a compact document but the cells
+#[derive(Serialize, Deserialize)]
+struct FpbHeader {
+    version: u64,
+    tileset: Option<TileSetInfo>,
+    #[serde(default)]
+    palette: Vec<Color>,
+    #[serde(default)]
+    metadata: Metadata,
+    /// every distinct cell of the document, cells refer to them by position counting from 1
+    tiles: Vec<TileState>,
+    frames: Vec<FpbFrame>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FpbFrame {
+    delay: u32,
+    width: usize,
+    height: usize,
+    layers: Vec<FpbLayer>,
+}
 
-pub fn write_palette(palette: &Vec<egui::Color32>, path: &Path) -> Result<(), Box<dyn Error>> {
-    fn to_serializable_palette(p: &Vec<egui::Color32>) -> Vec<(u8, u8, u8)> {
-        let mut p1 = Vec::new();
-        for c in p {
-            let (r, g, b, _) = c.to_tuple();
-            p1.push((r, g, b));
+#[derive(Serialize, Deserialize)]
+struct FpbLayer {
+    name: String,
+    visible: bool,
+    locked: bool,
+}
+
+// unsigned LEB128
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, FileErrorKind> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = bytes.split_first() else {
+            return Err(FileErrorKind::Invalid("truncated cell data".to_string()));
+        };
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(FileErrorKind::Invalid("invalid cell data".to_string()))
+}
+
+/// writes the document in the compact format
+///
+/// a json header holds everything but the cells, then every layer follows as runs of equal cells,
+/// each run being its length and the cell as an index into the distinct cells of the header
+pub fn write_fpb(
+    u: &CanvasFileRef,
+    mut writer: impl Write,
+    compress: bool,
+) -> Result<(), FileErrorKind> {
+    let mut tiles = Vec::new();
+    let mut tile_ids: HashMap<TileState, u64> = HashMap::new();
+    let mut frames = Vec::with_capacity(u.animation.frames.len());
+    let mut cells = Vec::new();
+    for frame in &u.animation.frames {
+        let canvas = &frame.canvas;
+        let size = canvas.width * canvas.height;
+        for layer in &canvas.layers {
+            let mut ids = (0..size).map(|i| match layer.cells.get(i).copied().flatten() {
+                None => 0,
+                Some(tile) => *tile_ids.entry(tile).or_insert_with(|| {
+                    tiles.push(tile);
+                    tiles.len() as u64
+                }),
+            });
+            let Some(mut run_id) = ids.next() else {
+                continue;
+            };
+            let mut run_len = 1;
+            for id in ids {
+                if id == run_id {
+                    run_len += 1;
+                } else {
+                    write_varint(&mut cells, run_len);
+                    write_varint(&mut cells, run_id);
+                    (run_id, run_len) = (id, 1);
+                }
+            }
+            write_varint(&mut cells, run_len);
+            write_varint(&mut cells, run_id);
         }
-        p1
+        frames.push(FpbFrame {
+            delay: frame.delay,
+            width: canvas.width,
+            height: canvas.height,
+            layers: canvas
+                .layers
+                .iter()
+                .map(|layer| FpbLayer {
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    locked: layer.locked,
+                })
+                .collect(),
+        });
+    }
+    let header = serde_json::to_vec(&FpbHeader {
+        version: FORMAT_VERSION,
+        tileset: Some(u.tileset.clone()),
+        palette: u.palette.to_vec(),
+        metadata: u.metadata.clone(),
+        tiles,
+        frames,
+    })?;
+
+    let mut bytes = Vec::with_capacity(FPB_MAGIC.len() + 4 + header.len() + cells.len());
+    bytes.extend_from_slice(FPB_MAGIC);
+    bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&cells);
+    if compress {
+        let mut writer = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        writer.write_all(&bytes)?;
+        writer.finish()?.flush()?;
+    } else {
+        writer.write_all(&bytes)?;
+        writer.flush()?;
     }
-    let palette = to_serializable_palette(palette);
-    let file = File::create(path)?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, &palette)?;
     Ok(())
 }
 
-pub fn load_palette(path: &Path) -> Result<Vec<egui::Color32>, Box<dyn Error>> {
-    fn to_color_vec(vec: Vec<(u8, u8, u8)>) -> Vec<egui::Color32> {
-        let mut p = Vec::with_capacity(vec.len());
-        for (r, g, b) in vec {
-            p.push(egui::Color32::from_rgb(r, g, b));
+/// reads a document written by [`write_fpb`], compressed or not
+pub fn read_fpb(mut reader: impl BufRead) -> Result<CanvasFile, FileErrorKind> {
+    let mut bytes = Vec::new();
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        flate2::read::GzDecoder::new(reader).read_to_end(&mut bytes)?;
+    } else {
+        reader.read_to_end(&mut bytes)?;
+    }
+    let Some(rest) = bytes.strip_prefix(FPB_MAGIC) else {
+        return Err(FileErrorKind::Invalid(
+            "not a fakepaint document".to_string(),
+        ));
+    };
+    let header_len = match rest.get(..4) {
+        Some(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+        None => 0,
+    };
+    let Some(header) = rest.get(4..4 + header_len) else {
+        return Err(FileErrorKind::Invalid("truncated header".to_string()));
+    };
+    let header: FpbHeader = serde_json::from_slice(header)?;
+    if header.version > FORMAT_VERSION {
+        return Err(FileErrorKind::Invalid(format!(
+            "written by a newer fakepaint, format version {} is not supported",
+            header.version
+        )));
+    }
+
+    let mut cells = &rest[4 + header_len..];
+    let mut frames = Vec::with_capacity(header.frames.len());
+    for frame in header.frames {
+        let Some(size) = validate::canvas_size(frame.width, frame.height) else {
+            return Err(too_large(frame.width, frame.height));
+        };
+        if frame.layers.is_empty() || frame.layers.len() > crate::canvas::MAX_LAYERS {
+            return Err(FileErrorKind::Invalid(format!(
+                "invalid fpb layer count: {}",
+                frame.layers.len()
+            )));
+        }
+        let mut layers = Vec::with_capacity(frame.layers.len());
+        for layer in frame.layers {
+            let mut layer_cells = Vec::new();
+            while layer_cells.len() < size {
+                let run_len = read_varint(&mut cells)? as usize;
+                let tile =
+                    match read_varint(&mut cells)? as usize {
+                        0 => None,
+                        id => Some(*header.tiles.get(id - 1).ok_or_else(|| {
+                            FileErrorKind::Invalid(format!("unknown cell {}", id))
+                        })?),
+                    };
+                if run_len == 0 || run_len > size - layer_cells.len() {
+                    return Err(FileErrorKind::Invalid("invalid cell data".to_string()));
+                }
+                layer_cells.resize(layer_cells.len() + run_len, tile);
+            }
+            layers.push(Layer {
+                name: layer.name,
+                visible: layer.visible,
+                locked: layer.locked,
+                cells: layer_cells,
+            });
+        }
+        frames.push(Frame {
+            delay: frame.delay,
+            canvas: Canvas {
+                width: frame.width,
+                height: frame.height,
+                layers,
+            },
+        });
+    }
+    let animation = if frames.is_empty() {
+        Animation::from(Canvas::default())
+    } else {
+        Animation { frames }
+    };
+    Ok(CanvasFile {
+        tileset: header.tileset,
+        palette: header.palette,
+        metadata: header.metadata,
+        animation,
+    })
+}
+
+pub fn write_palette(palette: &[Color], path: &Path) -> Result<(), FileError> {
+    with_path(path, || {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, palette)?;
+        Ok(())
+    })
+}
+
+pub fn load_palette(path: &Path) -> Result<Vec<Color>, FileError> {
+    with_path(path, || {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let palette: Vec<Color> = serde_json::from_reader(reader)?;
+        Ok(palette)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        flate2::read::GzDecoder::new(bytes)
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    // one 2x1 layer: '@' white on blue, then a transparent cell
+    fn xp_fixture() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(-1i32).to_le_bytes());
+        bytes.extend_from_slice(&1i32.to_le_bytes());
+        bytes.extend_from_slice(&2i32.to_le_bytes());
+        bytes.extend_from_slice(&1i32.to_le_bytes());
+        bytes.extend_from_slice(&64i32.to_le_bytes());
+        bytes.extend_from_slice(&[255, 255, 255, 0, 0, 255]);
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 255, 0, 255]);
+        bytes
+    }
+
+    #[test]
+    fn test_read_xp() -> Result<(), Box<dyn Error>> {
+        let canvas = read_xp(gzip(&xp_fixture()).as_slice())?;
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_eq!(canvas.layers.len(), 1);
+        assert_eq!(
+            canvas.get_cell(0, 0, 0),
+            &Some(TileState {
+                idx: 64,
+                fc: Color::WHITE,
+                bc: Color::from_rgb(0, 0, 255),
+            })
+        );
+        assert_eq!(canvas.get_cell(0, 1, 0), &None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_xp_column_major() -> Result<(), Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&1i32.to_le_bytes());
+        bytes.extend_from_slice(&2i32.to_le_bytes());
+        bytes.extend_from_slice(&2i32.to_le_bytes());
+        for idx in 0..4i32 {
+            bytes.extend_from_slice(&idx.to_le_bytes());
+            bytes.extend_from_slice(&[255, 255, 255, 0, 0, 0]);
+        }
+        let canvas = read_xp(gzip(&bytes).as_slice())?;
+        assert_eq!(canvas.get_cell(0, 0, 1).unwrap().idx, 1);
+        assert_eq!(canvas.get_cell(0, 1, 0).unwrap().idx, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_canvas_file_tileset() -> Result<(), Box<dyn Error>> {
+        let mut animation = Animation::from(Canvas::with_size(2, 2));
+        animation.frames.push(Frame {
+            delay: 30,
+            canvas: Canvas::with_size(2, 2),
+        });
+        let tileset = TileSetInfo {
+            path: "fonts/12x16.png".to_string(),
+            glyph_width: 12,
+            ..Default::default()
+        };
+        let mut metadata = Metadata {
+            title: "title screen".to_string(),
+            ..Default::default()
+        };
+        metadata.extra.insert("license".to_string(), "CC0".into());
+        let string = serde_json::to_string(&Envelope {
+            version: FORMAT_VERSION,
+            document: &CanvasFileRef {
+                tileset: &tileset,
+                palette: &[Color::BLACK],
+                metadata: &metadata,
+                animation: &animation,
+            },
+        })?;
+        let file: CanvasFile = serde_json::from_value(migrate(serde_json::from_str(&string)?)?)?;
+        assert_eq!(file.tileset, Some(tileset));
+        assert_eq!(file.palette, vec![Color::BLACK]);
+        assert_eq!(file.metadata, metadata);
+        assert_eq!(file.animation.frames.len(), 2);
+        assert_eq!(file.animation.frames[1].delay, 30);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate() -> Result<(), Box<dyn Error>> {
+        let value = migrate(serde_json::from_str(
+            r#"{"width":1,"height":1,"cells":[null]}"#,
+        )?)?;
+        assert_eq!(value["version"], FORMAT_VERSION);
+        assert_eq!(value["frames"][0]["width"], 1);
+        let file: CanvasFile = serde_json::from_value(value)?;
+        assert_eq!(file.tileset, None);
+        assert!(file.palette.is_empty());
+        assert_eq!(file.animation.frames[0].canvas.layers.len(), 1);
+
+        let file: CanvasFile = serde_json::from_value(migrate(serde_json::from_str(
+            r#"{"tileset":{"path":"a.png","columns":16,"rows":16,"glyph_width":8,"glyph_height":8},
+                "width":1,"height":1,"layers":[{"name":"a","visible":true,"locked":false,"cells":[null]}]}"#,
+        )?)?)?;
+        assert_eq!(file.tileset.unwrap().glyph_width, 8);
+        assert_eq!(file.animation.frames[0].canvas.layers[0].name, "a");
+
+        assert!(migrate(serde_json::from_str(r#"{"version":99,"frames":[]}"#)?).is_err());
+        assert!(migrate(serde_json::from_str("[]")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = std::env::temp_dir().join("fakepaint-test-load-errors");
+        std::fs::create_dir_all(&dir).unwrap();
+        let missing = dir.join("missing.json");
+        let err = load_canvas_from_file(&missing).err().unwrap();
+        assert_eq!(err.path, missing);
+        assert!(matches!(err.kind, FileErrorKind::Io(_)));
+        assert!(err.to_string().contains("missing.json"));
+
+        let broken = dir.join("broken.xp");
+        std::fs::write(&broken, gzip(&0i32.to_le_bytes())).unwrap();
+        let err = load_canvas_from_file(&broken).err().unwrap();
+        assert!(matches!(err.kind, FileErrorKind::Invalid(_)));
+        let err = load_palette(&broken).err().unwrap();
+        assert!(matches!(err.kind, FileErrorKind::Json(_)));
+    }
+
+    #[test]
+    fn test_xp_round_trip() -> Result<(), Box<dyn Error>> {
+        let canvas = read_xp(gzip(&xp_fixture()).as_slice())?;
+        let mut written = Vec::new();
+        write_xp(&canvas, &mut written)?;
+        assert_eq!(gunzip(&written), xp_fixture());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fpb_round_trip() -> Result<(), Box<dyn Error>> {
+        let mut canvas = Canvas::with_size(3, 2);
+        let tile = TileState {
+            idx: 64,
+            fc: Color::WHITE,
+            bc: Color::BLACK,
+        };
+        canvas.layers[0].cells[1] = Some(tile);
+        canvas.layers[0].cells[2] = Some(tile);
+        canvas.layers[0].cells[5] = Some(TileState { idx: 300, ..tile });
+        canvas.layers[0].locked = true;
+        let mut animation = Animation::from(canvas);
+        animation.frames.push(Frame {
+            delay: 30,
+            canvas: Canvas::with_size(1, 1),
+        });
+        let metadata = Metadata {
+            author: "me".to_string(),
+            ..Default::default()
+        };
+        let document = CanvasFileRef {
+            tileset: &TileSetInfo::default(),
+            palette: &[Color::WHITE],
+            metadata: &metadata,
+            animation: &animation,
+        };
+        for compress in [false, true] {
+            let mut written = Vec::new();
+            write_fpb(&document, &mut written, compress)?;
+            assert_eq!(written.starts_with(&GZIP_MAGIC), compress);
+            let file = read_fpb(written.as_slice())?;
+            assert_eq!(file.tileset, Some(TileSetInfo::default()));
+            assert_eq!(file.palette, vec![Color::WHITE]);
+            assert_eq!(file.metadata, metadata);
+            assert_eq!(file.animation.frames.len(), 2);
+            assert_eq!(file.animation.frames[1].delay, 30);
+            let layer = &file.animation.frames[0].canvas.layers[0];
+            assert!(layer.locked);
+            assert_eq!(layer.cells, animation.frames[0].canvas.layers[0].cells);
+        }
+
+        let mut written = Vec::new();
+        write_fpb(&document, &mut written, false)?;
+        written.pop();
+        assert!(matches!(
+            read_fpb(written.as_slice()),
+            Err(FileErrorKind::Invalid(_))
+        ));
+
+        // a frame without layers, the editor needs one to draw on
+        let header = serde_json::to_vec(&FpbHeader {
+            version: FORMAT_VERSION,
+            tileset: None,
+            palette: Vec::new(),
+            metadata: Metadata::default(),
+            tiles: Vec::new(),
+            frames: vec![FpbFrame {
+                delay: 0,
+                width: 2,
+                height: 2,
+                layers: Vec::new(),
+            }],
+        })?;
+        let mut bytes = FPB_MAGIC.to_vec();
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        assert!(matches!(
+            read_fpb(bytes.as_slice()),
+            Err(FileErrorKind::Invalid(_))
+        ));
+
+        // a frame whose cells would not fit in memory, followed by a single run covering it
+        let header = serde_json::to_vec(&FpbHeader {
+            version: FORMAT_VERSION,
+            tileset: None,
+            palette: Vec::new(),
+            metadata: Metadata::default(),
+            tiles: Vec::new(),
+            frames: vec![FpbFrame {
+                delay: 0,
+                width: usize::MAX,
+                height: 2,
+                layers: vec![FpbLayer {
+                    name: String::new(),
+                    visible: true,
+                    locked: false,
+                }],
+            }],
+        })?;
+        let mut bytes = FPB_MAGIC.to_vec();
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        write_varint(&mut bytes, u64::MAX);
+        write_varint(&mut bytes, 0);
+        assert!(matches!(
+            read_fpb(bytes.as_slice()),
+            Err(FileErrorKind::Corrupt(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_json_too_large() {
+        // a frame without layers gets an empty one of its size
+        for (width, height) in [(100_000, 100_000), (usize::MAX, 2)] {
+            let value = migrate(serde_json::json!({
+                "version": 2,
+                "frames": [{"width": width, "height": height}],
+            }))
+            .unwrap();
+            assert!(serde_json::from_value::<CanvasFile>(value).is_err());
+        }
+    }
+
+    #[test]
+    fn test_read_xp_too_large() {
+        let mut bytes = Vec::new();
+        for n in [1i32, 100_000, 100_000] {
+            bytes.extend_from_slice(&n.to_le_bytes());
         }
-        p
+        assert!(matches!(
+            read_xp(gzip(&bytes).as_slice()),
+            Err(FileErrorKind::Corrupt(_))
+        ));
     }
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let palette: Vec<(u8, u8, u8)> = serde_json::from_reader(reader)?;
-    Ok(to_color_vec(palette))
 }
@@ -0,0 +1,326 @@
+use eframe::egui;
+use fakepaint::animation::{self, Animation, Frame};
+use fakepaint::canvas::Canvas;
+use fakepaint::undo::History;
+use rust_i18n::t;
+
+/// a frame together with its own undo history
+struct FrameEdit {
+    canvas: Canvas,
+    history: History,
+    delay: u32,
+}
+
+impl From<Frame> for FrameEdit {
+    fn from(frame: Frame) -> Self {
+        Self {
+            canvas: frame.canvas,
+            history: History::new(),
+            delay: frame.delay,
+        }
+    }
+}
+
+pub enum FrameAction {
+    Select(usize),
+    Add,
+    Duplicate,
+    Delete,
+    MoveLeft,
+    MoveRight,
+    TogglePlay,
+}
+
+/// the frames of the document
+///
+/// the edited frame lives in the canvas and history of the app, its slot here only keeps the delay
+pub struct FrameStrip {
+    frames: Vec<FrameEdit>,
+    cur: usize,
+    pub onion_skin: bool,
+    playing_since: Option<f64>,
+    /// frames were added, removed, reordered or retimed since the last save
+    modified: bool,
+}
+
+impl FrameStrip {
+    /// takes the frames of the animation and hands out the first one for editing
+    pub fn new(animation: Animation) -> (Self, Canvas) {
+        let mut frames: Vec<FrameEdit> =
+            animation.frames.into_iter().map(FrameEdit::from).collect();
+        let canvas = std::mem::take(&mut frames[0].canvas);
+        (
+            Self {
+                frames,
+                cur: 0,
+                onion_skin: false,
+                playing_since: None,
+                modified: false,
+            },
+            canvas,
+        )
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing_since.is_some()
+    }
+
+    /// whether the document differs from the last save, `history` being the one of the edited frame
+    pub fn is_dirty(&self, history: &History) -> bool {
+        self.modified
+            || history.is_dirty()
+            || self.frames.iter().any(|frame| frame.history.is_dirty())
+    }
+
+    pub fn mark_saved(&mut self, history: &mut History) {
+        self.modified = false;
+        history.mark_saved();
+        self.frames
+            .iter_mut()
+            .for_each(|frame| frame.history.mark_saved());
+    }
+
+    /// marks the document as changed by something outside the histories
+    pub fn mark_modified(&mut self) {
+        self.modified = true;
+    }
+
+    fn switch_to(&mut self, idx: usize, canvas: &mut Canvas, history: &mut History) {
+        if idx == self.cur || idx >= self.frames.len() {
+            return;
+        }
+        let cur = &mut self.frames[self.cur];
+        std::mem::swap(&mut cur.canvas, canvas);
+        std::mem::swap(&mut cur.history, history);
+        let next = &mut self.frames[idx];
+        std::mem::swap(&mut next.canvas, canvas);
+        std::mem::swap(&mut next.history, history);
+        self.cur = idx;
+    }
+
+    /// every frame, `canvas` being the edited one
+    pub fn animation(&self, canvas: &Canvas) -> Animation {
+        let frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| Frame {
+                delay: frame.delay,
+                canvas: if i == self.cur {
+                    canvas.clone()
+                } else {
+                    frame.canvas.clone()
+                },
+            })
+            .collect();
+        Animation { frames }
+    }
+
+    /// the history of every frame with the canvas it is at, `history` and `canvas` being the edited ones
+    pub fn histories<'a>(
+        &'a self,
+        history: &'a History,
+        canvas: &'a Canvas,
+    ) -> Vec<(&'a History, &'a Canvas)> {
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                if i == self.cur {
+                    (history, canvas)
+                } else {
+                    (&frame.history, &frame.canvas)
+                }
+            })
+            .collect()
+    }
+
+    /// every frame for changing it outside the histories, `canvas` being the edited one
+    pub fn canvases_mut<'a>(
+        &'a mut self,
+        canvas: &'a mut Canvas,
+    ) -> impl Iterator<Item = &'a mut Canvas> {
+        let cur = self.cur;
+        let mut canvas = Some(canvas);
+        self.frames
+            .iter_mut()
+            .enumerate()
+            .filter_map(move |(i, frame)| {
+                if i == cur {
+                    canvas.take()
+                } else {
+                    Some(&mut frame.canvas)
+                }
+            })
+    }
+
+    /// hands the histories read back for the frames to them, the one of the edited frame going to `history`
+    pub fn set_histories(&mut self, histories: Vec<History>, history: &mut History) {
+        for (i, loaded) in histories.into_iter().enumerate() {
+            if i == self.cur {
+                *history = loaded;
+            } else if let Some(frame) = self.frames.get_mut(i) {
+                frame.history = loaded;
+            }
+        }
+    }
+
+    /// the previous and next frame drawn faintly under the edited one
+    pub fn onion_skins(&self) -> Vec<&Canvas> {
+        if !self.onion_skin || self.is_playing() {
+            return Vec::new();
+        }
+        let cur = self.cur;
+        [cur.wrapping_sub(1), cur + 1]
+            .into_iter()
+            .filter_map(|i| self.frames.get(i))
+            .map(|frame| &frame.canvas)
+            .collect()
+    }
+
+    /// advances the frame while playing
+    pub fn play(&mut self, ctx: &egui::Context, canvas: &mut Canvas, history: &mut History) {
+        if let Some(since) = self.playing_since {
+            let time = ((ctx.input(|i| i.time) - since) * 1000.0) as u64;
+            let idx = animation::frame_at(self.frames.iter().map(|frame| frame.delay), time);
+            self.switch_to(idx, canvas, history);
+            ctx.request_repaint();
+        }
+    }
+
+    pub fn apply(
+        &mut self,
+        ctx: &egui::Context,
+        action: FrameAction,
+        canvas: &mut Canvas,
+        history: &mut History,
+    ) {
+        let cur = self.cur;
+        match action {
+            FrameAction::Select(idx) => self.switch_to(idx, canvas, history),
+            FrameAction::Add | FrameAction::Duplicate => {
+                let mut new_canvas = canvas.clone();
+                if let FrameAction::Add = action {
+                    new_canvas
+                        .layers
+                        .iter_mut()
+                        .for_each(|layer| layer.cells.fill(None));
+                }
+                self.frames.insert(
+                    cur + 1,
+                    FrameEdit {
+                        canvas: new_canvas,
+                        history: History::new(),
+                        delay: self.frames[cur].delay,
+                    },
+                );
+                self.switch_to(cur + 1, canvas, history);
+                self.modified = true;
+            }
+            FrameAction::Delete => {
+                if self.frames.len() > 1 {
+                    self.frames.remove(cur);
+                    self.cur = cur.min(self.frames.len() - 1);
+                    let next = &mut self.frames[self.cur];
+                    *canvas = std::mem::take(&mut next.canvas);
+                    *history = std::mem::take(&mut next.history);
+                    self.modified = true;
+                }
+            }
+            FrameAction::MoveLeft => {
+                if cur > 0 {
+                    self.frames.swap(cur, cur - 1);
+                    self.cur -= 1;
+                    self.modified = true;
+                }
+            }
+            FrameAction::MoveRight => {
+                if cur + 1 < self.frames.len() {
+                    self.frames.swap(cur, cur + 1);
+                    self.cur += 1;
+                    self.modified = true;
+                }
+            }
+            FrameAction::TogglePlay => {
+                if self.is_playing() {
+                    self.playing_since = None;
+                } else {
+                    let offset: u64 = self.frames[..cur]
+                        .iter()
+                        .map(|frame| frame.delay.max(1) as u64)
+                        .sum();
+                    self.playing_since = Some(ctx.input(|i| i.time) - offset as f64 / 1000.0);
+                }
+            }
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<FrameAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.label(format!("{}: ", t!("frame")));
+            let play = if self.is_playing() { "⏸" } else { "▶" };
+            if ui.button(play).on_hover_text(t!("play")).clicked() {
+                action = Some(FrameAction::TogglePlay);
+            }
+            ui.toggle_value(&mut self.onion_skin, t!("onion_skin"));
+            ui.separator();
+            if ui.button("＋").on_hover_text(t!("add_frame")).clicked() {
+                action = Some(FrameAction::Add);
+            }
+            if ui
+                .button("⧉")
+                .on_hover_text(t!("duplicate_frame"))
+                .clicked()
+            {
+                action = Some(FrameAction::Duplicate);
+            }
+            if ui
+                .add_enabled(self.frames.len() > 1, egui::Button::new("－"))
+                .on_hover_text(t!("delete"))
+                .clicked()
+            {
+                action = Some(FrameAction::Delete);
+            }
+            if ui.button("⬅").clicked() {
+                action = Some(FrameAction::MoveLeft);
+            }
+            if ui.button("➡").clicked() {
+                action = Some(FrameAction::MoveRight);
+            }
+            ui.separator();
+            ui.label(t!("delay"));
+            if ui
+                .add(
+                    egui::DragValue::new(&mut self.frames[self.cur].delay)
+                        .clamp_range(core::ops::RangeInclusive::new(1, 10000))
+                        .suffix("ms"),
+                )
+                .changed()
+            {
+                self.modified = true;
+            }
+        });
+        egui::ScrollArea::horizontal()
+            .id_source("frame-strip")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for i in 0..self.frames.len() {
+                        if ui
+                            .selectable_label(i == self.cur, format!("{}", i + 1))
+                            .clicked()
+                        {
+                            action = Some(FrameAction::Select(i));
+                        }
+                    }
+                });
+            });
+        action
+    }
+}
+
+impl Default for FrameStrip {
+    fn default() -> Self {
+        Self::new(Animation::from(Canvas::default())).0
+    }
+}
@@ -0,0 +1,157 @@
+//! the undo trees of a document, kept next to it so branches outlive the session
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::Canvas;
+use crate::file::{with_path, FileError, FileErrorKind};
+use crate::undo::History;
+
+const HISTORY_VERSION: u64 = 1;
+
+#[derive(Serialize)]
+struct HistoryFileRef<'a> {
+    version: u64,
+    frames: Vec<FrameHistoryRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct FrameHistoryRef<'a> {
+    /// of the canvas the history is at, to tell whether the document changed without it
+    fingerprint: u64,
+    history: &'a History,
+}
+
+#[derive(Deserialize)]
+struct HistoryFile {
+    version: u64,
+    frames: Vec<FrameHistory>,
+}
+
+#[derive(Deserialize)]
+struct FrameHistory {
+    fingerprint: u64,
+    history: History,
+}
+
+/// fnv-1a of the canvas as json
+fn fingerprint(canvas: &Canvas) -> u64 {
+    let bytes = serde_json::to_vec(canvas).unwrap_or_default();
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// where the undo trees of `document` are kept, a hidden file next to it
+pub fn history_path(document: &Path) -> Option<PathBuf> {
+    let mut name = OsString::from(".");
+    name.push(document.file_name()?);
+    name.push(".history.gz");
+    Some(document.with_file_name(name))
+}
+
+/// writes the history of every frame, each with the canvas it is at
+pub fn write_history(frames: &[(&History, &Canvas)], path: &Path) -> Result<(), FileError> {
+    with_path(path, || {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+        serde_json::to_writer(
+            &mut encoder,
+            &HistoryFileRef {
+                version: HISTORY_VERSION,
+                frames: frames
+                    .iter()
+                    .map(|&(history, canvas)| FrameHistoryRef {
+                        fingerprint: fingerprint(canvas),
+                        history,
+                    })
+                    .collect(),
+            },
+        )?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    })
+}
+
+/// reads the histories back for the frames of the loaded document, marked as saved
+///
+/// gives None when they do not fit the frames any more, such as after editing the document elsewhere,
+/// and an error when the file matches the frames but its steps are broken
+pub fn load_history(path: &Path, canvases: &[&Canvas]) -> Result<Option<Vec<History>>, FileError> {
+    with_path(path, || {
+        let reader = BufReader::new(File::open(path)?);
+        let file: HistoryFile = serde_json::from_reader(flate2::read::GzDecoder::new(reader))?;
+        if file.version != HISTORY_VERSION
+            || file.frames.len() != canvases.len()
+            || file
+                .frames
+                .iter()
+                .zip(canvases)
+                .any(|(frame, canvas)| frame.fingerprint != fingerprint(canvas))
+        {
+            return Ok(None);
+        }
+        file.frames
+            .into_iter()
+            .zip(canvases)
+            .map(|(frame, canvas)| {
+                let mut history = frame.history.validated(canvas).ok_or_else(|| {
+                    FileErrorKind::Corrupt("the undo steps do not fit the document".to_string())
+                })?;
+                history.mark_saved();
+                Ok(history)
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tool::Pen;
+    use crate::undo::Command;
+
+    #[test]
+    fn test_history_file() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            history_path(Path::new("maps/town.json")),
+            Some(PathBuf::from("maps/.town.json.history.gz"))
+        );
+
+        let mut canvas = Canvas::with_size(4, 2);
+        let pen = Pen::default();
+        let mut history = History::new();
+        for x in 0..3 {
+            let command = Command::new(x, 0, &pen, &None, false, &canvas);
+            history.push(&mut canvas, command);
+        }
+        history.undo(&mut canvas);
+        history.undo(&mut canvas);
+        let command = Command::new(3, 1, &pen, &None, false, &canvas);
+        history.push(&mut canvas, command);
+        let other = Canvas::with_size(1, 1);
+
+        let path =
+            std::env::temp_dir().join(format!("fakepaint-history-{}.gz", std::process::id()));
+        write_history(&[(&history, &canvas), (&History::new(), &other)], &path)?;
+        assert!(load_history(&path, &[&canvas])?.is_none());
+        assert!(load_history(&path, &[&other, &canvas])?.is_none());
+
+        let mut histories = load_history(&path, &[&canvas, &other])?.unwrap();
+        std::fs::remove_file(&path)?;
+        let loaded = &mut histories[0];
+        assert!(!loaded.is_dirty());
+        assert_eq!(loaded.branch_count(1), 2);
+        // the abandoned branch came back with the file
+        assert!(loaded.switch_branch(&mut canvas, true));
+        assert!(canvas.get_cell(0, 2, 0).is_some());
+        assert!(canvas.get_cell(0, 3, 1).is_none());
+        assert!(loaded.is_dirty());
+        Ok(())
+    }
+}
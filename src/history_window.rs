@@ -0,0 +1,151 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eframe::egui;
+use rust_i18n::t;
+
+use fakepaint::undo::{History, Label};
+
+pub enum HistoryAction {
+    /// undo and redo until the canvas is at that step
+    Jump(usize),
+    /// show the canvas as it is at that step
+    Preview(usize),
+    /// go to the previous or next branch
+    SwitchBranch { forward: bool },
+    /// go back to the state at that time, in seconds since the unix epoch
+    TravelTo(u64),
+}
+
+fn xy((x, y): (usize, usize)) -> String {
+    format!("({}, {})", x, y)
+}
+
+fn label_text(label: &Label) -> String {
+    match label {
+        Label::Start => t!("step_original"),
+        Label::Point { x, y, erase: false } => t!("step_point", x = x, y = y),
+        Label::Point { x, y, erase: true } => t!("step_erase", x = x, y = y),
+        Label::Stroke { cells } => t!("step_stroke", cells = cells),
+        Label::Fill { x, y } => t!("step_fill", x = x, y = y),
+        Label::Replace { x, y } => t!("step_replace", x = x, y = y),
+        Label::Rect { from, to } => t!("step_rect", from = xy(*from), to = xy(*to)),
+        Label::Ellipse { from, to } => t!("step_ellipse", from = xy(*from), to = xy(*to)),
+        Label::Line { from, to } => t!("step_line", from = xy(*from), to = xy(*to)),
+        Label::Text { chars } => t!("step_text", chars = chars),
+        Label::Paste { x, y } => t!("step_paste", x = x, y = y),
+        Label::Clear { from, to } => t!("step_clear", from = xy(*from), to = xy(*to)),
+        Label::Move { from, to } => t!("step_move", from = xy(*from), to = xy(*to)),
+        Label::Resize { width, height } => t!("step_resize", width = width, height = height),
+        Label::AddLayer { name } => t!("step_add_layer", name = name),
+        Label::RemoveLayer { name } => t!("step_remove_layer", name = name),
+        Label::MoveLayer { name } => t!("step_move_layer", name = name),
+        Label::RenameLayer { name } => t!("step_rename_layer", name = name),
+        Label::ShowLayer {
+            name,
+            visible: true,
+        } => t!("step_show_layer", name = name),
+        Label::ShowLayer {
+            name,
+            visible: false,
+        } => t!("step_hide_layer", name = name),
+        Label::LockLayer { name, locked: true } => t!("step_lock_layer", name = name),
+        Label::LockLayer {
+            name,
+            locked: false,
+        } => t!("step_unlock_layer", name = name),
+    }
+}
+
+/// how long ago `time` was, both in seconds since the unix epoch
+fn ago(time: u64, now: u64) -> String {
+    let secs = now.saturating_sub(time);
+    match secs {
+        0..=59 => t!("seconds_ago", n = secs),
+        60..=3599 => t!("minutes_ago", n = secs / 60),
+        3600..=86399 => t!("hours_ago", n = secs / 3600),
+        _ => t!("days_ago", n = secs / 86400),
+    }
+}
+
+/// the undo steps of the branch the edited frame is on, newest at the bottom
+#[derive(Default)]
+pub struct HistoryWindow {
+    open: bool,
+}
+
+impl HistoryWindow {
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, history: &History) -> Option<HistoryAction> {
+        let mut action = None;
+        let current = history.current();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        egui::Window::new(t!("history"))
+            .open(&mut self.open)
+            .default_height(320.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let branches = history.can_switch_branch();
+                    if ui
+                        .add_enabled(branches, egui::Button::new("⏴"))
+                        .on_hover_text(t!("previous_branch"))
+                        .clicked()
+                    {
+                        action = Some(HistoryAction::SwitchBranch { forward: false });
+                    }
+                    if ui
+                        .add_enabled(branches, egui::Button::new("⏵"))
+                        .on_hover_text(t!("next_branch"))
+                        .clicked()
+                    {
+                        action = Some(HistoryAction::SwitchBranch { forward: true });
+                    }
+                    ui.separator();
+                    let (first, last) = history.time_range();
+                    let mut time = history.time(current);
+                    let slider = egui::Slider::new(&mut time, first..=last)
+                        .custom_formatter(|time, _| ago(time as u64, now));
+                    if ui
+                        .add_enabled(first < last, slider)
+                        .on_hover_text(t!("time_travel"))
+                        .changed()
+                    {
+                        action = Some(HistoryAction::TravelTo(time));
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        // steps past the current one are those redo goes through
+                        let mut undone = false;
+                        for step in history.branch() {
+                            let mut text = label_text(history.label(step));
+                            let branches = history.branch_count(step);
+                            if branches > 1 {
+                                text = format!("{} ⑂{}", text, branches);
+                            }
+                            let text = if undone {
+                                egui::RichText::new(text).weak()
+                            } else {
+                                egui::RichText::new(text)
+                            };
+                            let res = ui
+                                .selectable_label(step == current, text)
+                                .on_hover_text(ago(history.time(step), now));
+                            if res.clicked() {
+                                action = Some(HistoryAction::Jump(step));
+                            } else if res.hovered() && step != current {
+                                action = Some(HistoryAction::Preview(step));
+                            }
+                            undone |= step == current;
+                        }
+                    });
+            });
+        action
+    }
+}
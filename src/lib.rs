@@ -0,0 +1,20 @@
+//! the fakepaint document model, editing commands and file formats, without any gui
+
+pub mod animation;
+pub mod ansi;
+pub mod canvas;
+pub mod color;
+pub mod cp437;
+pub mod file;
+pub mod history_file;
+pub mod recovery;
+pub mod selection;
+pub mod shape;
+pub mod text;
+pub mod tile;
+pub mod tool;
+pub mod undo;
+pub mod validate;
+
+pub use canvas::{Canvas, TileState};
+pub use color::Color;
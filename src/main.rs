@@ -1,28 +1,58 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod canvas;
+mod canvas_size;
+mod cli;
 mod color_editer;
+mod color_ext;
+mod export_ansi;
 mod export_image;
-mod file;
+mod frame_strip;
+mod history_window;
 mod image_button;
+mod metadata_window;
 mod new_file;
 mod setup;
-mod tile;
-mod undo;
+mod tile_texture;
+mod tileset_window;
+mod toast;
 
-use canvas::{Canvas, CanvasSizeEditWindow};
+use canvas::Canvas;
+use canvas_size::CanvasSizeEditWindow;
 use color_editer::PencilState;
+use color_ext::{ToColor, ToColor32};
 use eframe::egui;
-use file::{load_canvas_from_file, write_canvas_to_file};
+use fakepaint::animation::Animation;
+use fakepaint::{
+    canvas, cp437, file, history_file, recovery, selection, shape, text, tile, undo, validate,
+};
+use file::{load_canvas_from_file, write_canvas_to_file, CanvasFileRef, Metadata};
+use frame_strip::FrameStrip;
+use history_window::HistoryAction;
 use rust_i18n::t;
 use tile::TileSet;
+use tile_texture::TileTexture;
 
 rust_i18n::i18n!("locals", fallback = "zh-CN");
 
 const TILE_SIZE: f32 = 16.0;
 const TILE_SIZE_VEC2: egui::Vec2 = egui::Vec2::splat(16.0);
+const ONION_SKIN_ALPHA: f32 = 0.3;
+/// seconds between two copies of unsaved edits to the recovery file
+const AUTOSAVE_INTERVAL: f64 = 30.0;
 
 fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = cli::parse(&args) {
+        let result = command
+            .map_err(|e| format!("{}\n{}", e, cli::USAGE).into())
+            .and_then(cli::run);
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     tracing_subscriber::fmt::init();
     let opts = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(550.0, 540.0)),
@@ -31,25 +61,45 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "fakepaint",
         opts,
-        Box::new(|cc| Box::new(FakePaint::new(cc))),
+        // anything that is not a subcommand is a document to open, as file managers pass them
+        Box::new(|cc| Box::new(FakePaint::new(cc, args.into_iter().next()))),
     )
 }
 
 use undo::History;
 
-use color_editer::ToolEnum;
+use fakepaint::tool::ToolEnum;
 
 struct FakePaint {
     tile: TileSet,
+    tile_tex: TileTexture,
     pencil_state: PencilState,
     canvas: Canvas,
     rendering_scale: f32,
     editing_history: History,
+    frame_strip: FrameStrip,
+    /// the title last given to the window
+    window_title: String,
+    toasts: toast::Toasts,
+    /// when unsaved edits were last copied to the recovery file
+    last_autosave: f64,
     cur_cell: Option<(Option<canvas::TileState>, usize, usize)>,
+    /// the cell the last pencil stroke got to, where the next one is drawn from
+    stroke_from: Option<(usize, usize)>,
     editing_file_path: Option<String>,
+    renaming_layer: String,
+    selection: selection::Selection,
+    text_input: text::TextInput,
     new_file_window: new_file::NewFileWinodw,
     export_image_window: export_image::ExportImageWindow,
+    export_ansi_window: export_ansi::ExportAnsiWindow,
     canvas_size_window: CanvasSizeEditWindow,
+    tileset_window: tileset_window::TileSetWindow,
+    metadata: Metadata,
+    metadata_window: metadata_window::MetadataWindow,
+    history_window: history_window::HistoryWindow,
+    /// the step hovered in the history and the canvas as it was then, drawn instead of the canvas
+    history_preview: Option<((usize, usize), Canvas)>,
 }
 
 fn get_center_rect(rect: &egui::Rect, size: egui::Vec2) -> egui::Rect {
@@ -66,6 +116,39 @@ fn get_center_rect(rect: &egui::Rect, size: egui::Vec2) -> egui::Rect {
     )
 }
 
+/// the first few problems of a document, one per line
+fn describe_problems(problems: &[validate::Problem]) -> String {
+    const SHOWN: usize = 8;
+    let mut lines: Vec<String> = problems.iter().take(SHOWN).map(|p| p.to_string()).collect();
+    if problems.len() > SHOWN {
+        lines.push(format!("… +{}", problems.len() - SHOWN));
+    }
+    lines.join("\n")
+}
+
+/// shows what is wrong with the document and asks whether to repair it
+fn confirm_repair(problems: &[validate::Problem]) -> bool {
+    let repair = t!("repair");
+    let result = rfd::MessageDialog::new()
+        .set_title(t!("invalid_file"))
+        .set_description(format!(
+            "{}\n\n{}",
+            describe_problems(problems),
+            t!("repair_prompt")
+        ))
+        .set_level(rfd::MessageLevel::Warning)
+        .set_buttons(rfd::MessageButtons::OkCancelCustom(
+            repair.clone(),
+            t!("cancel"),
+        ))
+        .show();
+    match result {
+        rfd::MessageDialogResult::Ok => true,
+        rfd::MessageDialogResult::Custom(label) => label == repair,
+        _ => false,
+    }
+}
+
 fn get_grid_x_y(rect: egui::Rect, pos: egui::Pos2, size: egui::Vec2) -> (usize, usize) {
     let min = rect.min;
     let x = (pos.x - min.x) / size.x;
@@ -93,13 +176,17 @@ fn compute_grid_rect(
 }
 
 impl FakePaint {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    /// `open` is a document given on the command line, otherwise the one edited last is reopened
+    fn new(cc: &eframe::CreationContext<'_>, open: Option<String>) -> Self {
         use color_editer::StoragePen;
         let pen: StoragePen;
-        let canvas: Canvas;
-        let palette: Vec<egui::Color32>;
+        let mut animation = Animation::from(Canvas::default());
+        let mut metadata = Metadata::default();
+        let mut palette: Vec<egui::Color32>;
         let scale: f32;
         let mut editing_file_path: Option<String>;
+        let mut tileset_info: tile::TileSetInfo;
+        let mut load_error = None;
 
         if let Some(storage) = cc.storage {
             scale =
@@ -120,49 +207,402 @@ impl FakePaint {
                     .unwrap_or_else(|| String::new()),
             )
             .unwrap_or_else(|_| None);
+            tileset_info = serde_json::from_str(
+                &storage
+                    .get_string("tileset")
+                    .unwrap_or_else(|| String::new()),
+            )
+            .unwrap_or_else(|_| tile::TileSetInfo::default());
 
-            if let Some(path) = editing_file_path.clone() {
-                if let Ok(cc) = load_canvas_from_file(&std::path::Path::new(&path)) {
-                    canvas = cc;
-                } else {
-                    canvas = Canvas::default();
-                    editing_file_path = None;
-                }
-            } else {
-                canvas = serde_json::from_str(
+            if open.is_none() && editing_file_path.is_none() {
+                animation = serde_json::from_str(
                     &storage
                         .get_string("canvas")
                         .unwrap_or_else(|| String::new()),
                 )
-                .unwrap_or_else(|_| Canvas::default());
+                .unwrap_or_else(|_| Animation::from(Canvas::default()));
+                metadata =
+                    serde_json::from_str(&storage.get_string("metadata").unwrap_or_default())
+                        .unwrap_or_default();
             }
         } else {
             scale = 1.0;
             pen = StoragePen::default();
-            canvas = Canvas::default();
             palette = vec![egui::Color32::WHITE, egui::Color32::BLACK];
             editing_file_path = None;
+            tileset_info = tile::TileSetInfo::default();
+        }
+
+        if open.is_some() {
+            editing_file_path = open;
+        }
+        if let Some(path) = editing_file_path.clone() {
+            match load_canvas_from_file(std::path::Path::new(&path)) {
+                Ok(cc) => {
+                    animation = cc.animation;
+                    metadata = cc.metadata;
+                    if !cc.palette.is_empty() {
+                        palette = cc.palette.into_iter().map(|c| c.to_color32()).collect();
+                    }
+                    if let Some(info) = cc.tileset {
+                        tileset_info = info;
+                    }
+                }
+                Err(e) => {
+                    editing_file_path = None;
+                    load_error = Some(e);
+                }
+            }
+        }
+
+        let document_path = editing_file_path.as_deref().map(std::path::Path::new);
+        let mut recovered = false;
+        if let Some(path) = recovery::find_recovery(document_path) {
+            let restore = t!("restore");
+            let result = rfd::MessageDialog::new()
+                .set_title(t!("recovery"))
+                .set_description(t!("restore_prompt"))
+                .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                    restore.clone(),
+                    t!("discard_recovery"),
+                ))
+                .show();
+            match result {
+                rfd::MessageDialogResult::Ok => recovered = true,
+                rfd::MessageDialogResult::Custom(label) => recovered = label == restore,
+                _ => {}
+            }
+            if recovered {
+                match load_canvas_from_file(&path) {
+                    Ok(cc) => {
+                        animation = cc.animation;
+                        metadata = cc.metadata;
+                        if !cc.palette.is_empty() {
+                            palette = cc.palette.into_iter().map(|c| c.to_color32()).collect();
+                        }
+                        if let Some(info) = cc.tileset {
+                            tileset_info = info;
+                        }
+                    }
+                    Err(e) => {
+                        recovered = false;
+                        load_error = Some(e);
+                    }
+                }
+            } else {
+                recovery::remove_recovery(document_path);
+            }
         }
 
         setup::custom_fonts(&cc.egui_ctx);
         cc.egui_ctx.set_visuals(egui::Visuals::dark());
-        let (tex_handle, image_data) = tile::load_texture(&cc.egui_ctx);
+        // the document is checked against the tileset that actually loaded
+        let mut tileset_error = None;
+        let tile = TileSet::load(&tileset_info).unwrap_or_else(|e| {
+            tileset_error = Some(e.to_string());
+            let default = tile::TileSetInfo::default();
+            TileSet::load(&default).unwrap_or_else(|e| {
+                tileset_error = Some(e.to_string());
+                // blank glyphs keep the app usable until another tileset is picked
+                let image = image::DynamicImage::new_rgba8(
+                    (default.columns * default.glyph_width) as u32,
+                    (default.rows * default.glyph_height) as u32,
+                );
+                TileSet::new(image, default)
+            })
+        });
+        let problems = validate::validate(&animation, tile.glyph_count());
+        validate::repair(&mut animation, tile.glyph_count());
+        let (frame_strip, canvas) = FrameStrip::new(animation);
         let mut r = Self {
-            tile: TileSet::new(image_data, tex_handle.unwrap(), 16, 16, TILE_SIZE_VEC2),
+            tile_tex: TileTexture::new(&cc.egui_ctx, &tile),
+            tile,
             pencil_state: PencilState::from(pen),
             canvas,
             editing_history: History::new(),
+            frame_strip,
+            window_title: String::new(),
+            toasts: toast::Toasts::default(),
+            last_autosave: 0.0,
             cur_cell: None,
+            stroke_from: None,
             editing_file_path,
+            renaming_layer: String::new(),
+            selection: selection::Selection::default(),
+            text_input: text::TextInput::default(),
             rendering_scale: scale,
             new_file_window: new_file::NewFileWinodw::default(),
             export_image_window: export_image::ExportImageWindow::default(),
+            export_ansi_window: export_ansi::ExportAnsiWindow::default(),
             canvas_size_window: CanvasSizeEditWindow::default(),
+            tileset_window: tileset_window::TileSetWindow::default(),
+            metadata,
+            metadata_window: metadata_window::MetadataWindow::default(),
+            history_window: history_window::HistoryWindow::default(),
+            history_preview: None,
         };
         r.pencil_state.palette = color_editer::Palette::from(palette);
+        if let Some(e) = load_error {
+            r.toasts.error(format!("{}: {}", t!("load_failed"), e));
+        }
+        if let Some(e) = tileset_error {
+            r.toasts.error(format!("{}: {}", t!("load_failed"), e));
+        }
+        if recovered {
+            r.frame_strip.mark_modified();
+        } else if problems.is_empty() {
+            r.restore_history();
+        }
+        if !problems.is_empty() {
+            r.frame_strip.mark_modified();
+            r.toasts.error(format!(
+                "{}\n{}",
+                t!("repaired_file"),
+                describe_problems(&problems)
+            ));
+        }
         r
     }
 
+    /// switches to the tileset, false when it does not load or the document does not fit it
+    /// and the user keeps the current one
+    fn set_tileset(&mut self, ctx: &egui::Context, info: &tile::TileSetInfo) -> bool {
+        if *info == self.tile.info {
+            return true;
+        }
+        let tile = match TileSet::load(info) {
+            Ok(tile) => tile,
+            Err(e) => {
+                self.toasts.error(format!("{}: {}", t!("load_failed"), e));
+                return false;
+            }
+        };
+        // cells past the glyphs of the new tileset can't be drawn with it
+        let glyph_count = tile.glyph_count();
+        let problems = validate::validate(&self.frame_strip.animation(&self.canvas), glyph_count);
+        if !problems.is_empty() {
+            if !confirm_repair(&problems) {
+                return false;
+            }
+            self.frame_strip
+                .canvases_mut(&mut self.canvas)
+                .for_each(|canvas| validate::repair_canvas(canvas, glyph_count));
+            self.frame_strip.mark_modified();
+        }
+        self.use_tileset(ctx, tile);
+        true
+    }
+
+    fn use_tileset(&mut self, ctx: &egui::Context, tile: TileSet) {
+        self.tile_tex = TileTexture::new(ctx, &tile);
+        self.tile = tile;
+        if self.pencil_state.idx >= self.tile.glyph_count() {
+            self.pencil_state.idx = 0;
+        }
+    }
+
+    /// replaces the document with the frames of `animation`, as it is on disk
+    fn load_document(&mut self, animation: Animation) {
+        (self.frame_strip, self.canvas) = FrameStrip::new(animation);
+        self.editing_history = History::new();
+        self.text_input.cancel();
+        self.metadata = Metadata::default();
+    }
+
+    /// checks a loaded document and asks whether to repair it when it is broken
+    ///
+    /// None when the user would rather not load it, otherwise whether it was repaired
+    fn accept_document(&mut self, animation: &mut Animation, glyph_count: usize) -> Option<bool> {
+        let problems = validate::validate(animation, glyph_count);
+        if problems.is_empty() {
+            return Some(false);
+        }
+        if !confirm_repair(&problems) {
+            return None;
+        }
+        validate::repair(animation, glyph_count);
+        Some(true)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.frame_strip.is_dirty(&self.editing_history)
+    }
+
+    /// keeps the edited layer on the canvas after undoing or removing layers
+    fn fit_layer(&mut self) {
+        let len = self.canvas.layers.len();
+        if self.pencil_state.layer >= len {
+            self.pencil_state.layer = len.saturating_sub(1);
+        }
+    }
+
+    fn document_path(&self) -> Option<&std::path::Path> {
+        self.editing_file_path.as_deref().map(std::path::Path::new)
+    }
+
+    /// the document as it would be saved
+    fn with_document<R>(&self, f: impl FnOnce(&CanvasFileRef) -> R) -> R {
+        let animation = self.frame_strip.animation(&self.canvas);
+        let palette: Vec<_> = self
+            .pencil_state
+            .palette_vec_ref()
+            .iter()
+            .map(|c| c.to_color())
+            .collect();
+        f(&CanvasFileRef {
+            tileset: &self.tile.info,
+            palette: &palette,
+            metadata: &self.metadata,
+            animation: &animation,
+        })
+    }
+
+    /// copies unsaved edits to the recovery file every [`AUTOSAVE_INTERVAL`] seconds
+    fn autosave(&mut self, ctx: &egui::Context) {
+        if !self.is_dirty() {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        let due = self.last_autosave + AUTOSAVE_INTERVAL;
+        if now < due {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(due - now));
+            return;
+        }
+        self.last_autosave = now;
+        let Some(path) = recovery::recovery_path(self.document_path()) else {
+            return;
+        };
+        if let Err(e) = self.with_document(|document| recovery::write_recovery(document, &path)) {
+            self.toasts
+                .error(format!("{}: {}", t!("autosave_failed"), e));
+        }
+    }
+
+    /// writes the document to its file, or to a picked one when it has none or `pick_path` is set
+    fn save_document(&mut self, pick_path: bool) -> bool {
+        let path = match (&self.editing_file_path, pick_path) {
+            (Some(path), false) => std::path::PathBuf::from(path),
+            _ => match rfd::FileDialog::new()
+                .set_title(t!("save"))
+                .add_filter("json", &["json"])
+                .add_filter("fpz", &["fpz"])
+                .add_filter("fpb", &["fpb"])
+                .add_filter("xp", &["xp"])
+                .save_file()
+            {
+                Some(path) => path,
+                None => return false,
+            },
+        };
+        // the histories are kept as they are so edits can still be undone after saving
+        if let Err(e) = self.with_document(|document| write_canvas_to_file(document, &path)) {
+            self.toasts.error(format!("{}: {}", t!("save_failed"), e));
+            return false;
+        }
+        if file::is_lossy_format(&path) {
+            // the frames, palette and metadata are only in memory, so this is an export
+            self.toasts.error(t!("saved_as_export"));
+            return false;
+        }
+        recovery::remove_recovery(self.document_path());
+        recovery::remove_recovery(Some(&path));
+        if let Some(string) = path.to_str() {
+            self.editing_file_path = Some(string.to_string());
+        }
+        self.frame_strip.mark_saved(&mut self.editing_history);
+        self.save_history(&path);
+        true
+    }
+
+    /// keeps the undo trees next to the saved document, so undoing and branches survive a restart
+    fn save_history(&mut self, document: &std::path::Path) {
+        let Some(path) = history_file::history_path(document) else {
+            return;
+        };
+        let histories = self
+            .frame_strip
+            .histories(&self.editing_history, &self.canvas);
+        if let Err(e) = history_file::write_history(&histories, &path) {
+            self.toasts
+                .error(format!("{}: {}", t!("save_history_failed"), e));
+        }
+    }
+
+    /// picks up the undo trees saved next to the document, when they still fit it
+    fn restore_history(&mut self) {
+        let Some(path) = self.document_path().and_then(history_file::history_path) else {
+            return;
+        };
+        if !path.exists() {
+            return;
+        }
+        let histories = self
+            .frame_strip
+            .histories(&self.editing_history, &self.canvas);
+        let canvases: Vec<_> = histories.into_iter().map(|(_, canvas)| canvas).collect();
+        match history_file::load_history(&path, &canvases) {
+            Ok(Some(histories)) => self
+                .frame_strip
+                .set_histories(histories, &mut self.editing_history),
+            Ok(None) => {}
+            Err(e) => self
+                .toasts
+                .error(format!("{}: {}", t!("load_history_failed"), e)),
+        }
+    }
+
+    /// asks what to do with unsaved edits, false when the user cancels
+    fn confirm_discard(&mut self) -> bool {
+        if !self.is_dirty() {
+            return true;
+        }
+        let (save, discard) = (t!("save"), t!("discard"));
+        let result = rfd::MessageDialog::new()
+            .set_title(t!("unsaved_changes"))
+            .set_description(t!("save_changes_prompt"))
+            .set_level(rfd::MessageLevel::Warning)
+            .set_buttons(rfd::MessageButtons::YesNoCancelCustom(
+                save.clone(),
+                discard.clone(),
+                t!("cancel"),
+            ))
+            .show();
+        // depending on the platform custom buttons come back as yes/no or as their label
+        let discarded = match result {
+            rfd::MessageDialogResult::Yes => return self.save_document(false),
+            rfd::MessageDialogResult::No => true,
+            rfd::MessageDialogResult::Custom(label) if label == save => {
+                return self.save_document(false)
+            }
+            rfd::MessageDialogResult::Custom(label) => label == discard,
+            _ => false,
+        };
+        if discarded {
+            recovery::remove_recovery(self.document_path());
+        }
+        discarded
+    }
+
+    /// the hovered step of the history while previewing it, the document otherwise
+    ///
+    /// only for painting, everything else reads and edits `self.canvas`
+    fn shown_canvas(&self) -> &Canvas {
+        self.history_preview
+            .as_ref()
+            .map_or(&self.canvas, |(_, preview)| preview)
+    }
+
+    fn window_title(&self) -> String {
+        let name = self
+            .editing_file_path
+            .as_ref()
+            .and_then(|path| std::path::Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| t!("untitled"));
+        let dirty = if self.is_dirty() { "*" } else { "" };
+        format!("{}{} - fakepaint", dirty, name)
+    }
+
     fn draw_pencil_state(&self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label(egui::RichText::new(format!("{}: ", t!("pen"))).size(24.0));
@@ -173,11 +613,11 @@ impl FakePaint {
             if ui.is_rect_visible(rect) {
                 ui.painter()
                     .rect_filled(rect, egui::Rounding::none(), self.pencil_state.bc);
-                self.tile.paint_in_rect(
+                self.tile_tex.paint_in_rect(
                     ui,
-                    get_center_rect(&rect, TILE_SIZE_VEC2),
+                    get_center_rect(&rect, self.tile_tex.preview_size(TILE_SIZE)),
                     self.pencil_state.idx,
-                    self.pencil_state.fc,
+                    self.pencil_state.fc.to_color(),
                     None,
                 );
             }
@@ -192,6 +632,23 @@ impl FakePaint {
         }
     }
 
+    /// holding shift constrains ellipses to circles
+    fn shape_to_xy(
+        &self,
+        ui: &egui::Ui,
+        start_xy: (usize, usize),
+        to_xy: (usize, usize),
+    ) -> (usize, usize) {
+        let tool = self.pencil_state.tool;
+        if matches!(tool, ToolEnum::EllipseFilled | ToolEnum::EllipseOutline)
+            && ui.input(|i| i.modifiers.shift)
+        {
+            shape::constrain_square(start_xy, to_xy, self.canvas.width, self.canvas.height)
+        } else {
+            to_xy
+        }
+    }
+
     fn draw_nib(
         &mut self,
         ui: &mut egui::Ui,
@@ -199,7 +656,6 @@ impl FakePaint {
         rect: egui::Rect,
         x: usize,
         y: usize,
-        rendering_canvas: &Canvas,
     ) {
         fn compute_color(c: egui::Color32) -> egui::Color32 {
             const ADD: u16 = 128;
@@ -212,42 +668,41 @@ impl FakePaint {
             let b = ((b + ADD) % (u8::MAX as u16)) as u8;
             egui::Color32::from_rgb(r, g, b)
         }
-        let cell = rendering_canvas.get_cell(x, y);
-        self.cur_cell = Some((*cell, x, y));
-        let pencil = &self.pencil_state;
-        if let Some((fc, bc)) = pencil.get_fc_bc(cell) {
-            if pencil.tool == ToolEnum::RectFilled && pencil.start_xy != None {
+        let cell = *self.canvas.get_cell(self.pencil_state.layer, x, y);
+        self.cur_cell = Some((cell, x, y));
+        let pencil = self.pencil_state.pen();
+        if let Some((fc, bc)) = pencil.get_fc_bc(&cell) {
+            if let (true, Some(start_xy)) = (pencil.tool.is_shape(), pencil.start_xy) {
                 let cur_tile_size_vec2 = rect.size();
-                let (mut start_x, mut start_y) = pencil.start_xy.unwrap();
-                let mut to_x = x;
-                let mut to_y = y;
-                if to_x < start_x {
-                    std::mem::swap(&mut start_x, &mut to_x)
-                }
-                if to_y < start_y {
-                    std::mem::swap(&mut start_y, &mut to_y)
-                }
-                for y in start_y..=to_y {
-                    for x in start_x..=to_x {
+                let tile = Some(canvas::TileState {
+                    idx: pencil.idx,
+                    fc,
+                    bc,
+                });
+                let to_xy = self.shape_to_xy(ui, start_xy, (x, y));
+                let cmd = undo::Command::new_shape(&pencil, tile, start_xy, to_xy);
+                for (x, y, c) in cmd.shape_cells() {
+                    if let Some(c) = c {
                         let rect = compute_grid_rect(start_pos, cur_tile_size_vec2, x, y);
-                        self.tile.paint_in_rect(ui, rect, pencil.idx, fc, Some(bc));
+                        self.tile_tex
+                            .paint_in_rect(ui, rect, c.idx, c.fc, Some(c.bc));
                     }
                 }
             } else {
-                self.tile.paint_in_rect(ui, rect, pencil.idx, fc, Some(bc))
+                self.tile_tex
+                    .paint_in_rect(ui, rect, pencil.idx, fc, Some(bc))
             }
         } else {
             let bc;
-            if let &Some(tile) = cell {
-                bc = tile.bc;
-                self.tile
-                    .paint_in_rect(ui, rect, tile.idx, tile.fc, Some(bc));
+            if let &Some(tile) = self.shown_canvas().composite_cell(x, y) {
+                bc = tile.bc.to_color32();
+                self.tile_tex
+                    .paint_in_rect(ui, rect, tile.idx, tile.fc, Some(tile.bc));
             } else {
                 bc = Self::get_gray(x, y);
                 ui.painter().rect_filled(rect, egui::Rounding::none(), bc)
             }
-            if pencil.tool == ToolEnum::RectFilled && pencil.start_xy != None {
-            } else {
+            if !(pencil.tool.is_shape() && pencil.start_xy.is_some()) {
                 ui.painter().rect_stroke(
                     rect,
                     egui::Rounding::none(),
@@ -257,22 +712,19 @@ impl FakePaint {
         }
     }
 
-    fn draw_canvas(
-        &mut self,
-        ui: &mut egui::Ui,
-        rendering_canvas: &Canvas,
-        render_size: egui::Vec2,
-    ) {
+    fn draw_canvas(&mut self, ui: &mut egui::Ui, render_size: egui::Vec2) {
         let (rect, res) = ui.allocate_exact_size(render_size, egui::Sense::drag());
         let hover_pos = res.hover_pos();
-        let cur_tile_size_vec2 = TILE_SIZE_VEC2 * self.rendering_scale;
+        let cur_tile_size_vec2 = self.tile_tex.tile_size * self.rendering_scale;
         let mut hover_xy: Option<(usize, usize, egui::Rect)> = None;
         if ui.is_rect_visible(rect) {
             let left_top = rect.left_top();
-            for y in 0..rendering_canvas.height {
-                for x in 0..rendering_canvas.width {
+            let shown = self.shown_canvas();
+            let onion_skins = self.frame_strip.onion_skins();
+            for y in 0..shown.height {
+                for x in 0..shown.width {
                     let rect = compute_grid_rect(left_top, cur_tile_size_vec2, x, y);
-                    let cell = rendering_canvas.get_cell(x, y);
+                    let cell = shown.composite_cell(x, y);
 
                     if hover_pos != None && rect.contains(hover_pos.unwrap()) {
                         hover_xy = Some((x, y, rect));
@@ -281,7 +733,7 @@ impl FakePaint {
                         let idx = c.idx;
                         let bc = c.bc;
                         let fc = c.fc;
-                        self.tile.paint_in_rect(ui, rect, idx, fc, Some(bc));
+                        self.tile_tex.paint_in_rect(ui, rect, idx, fc, Some(bc));
                     } else if ui.is_rect_visible(rect) {
                         ui.painter().rect_filled(
                             rect,
@@ -289,66 +741,289 @@ impl FakePaint {
                             Self::get_gray(x, y),
                         );
                     }
+                    for onion in &onion_skins {
+                        if x >= onion.width || y >= onion.height {
+                            continue;
+                        }
+                        if let Some(c) = onion.composite_cell(x, y) {
+                            self.tile_tex
+                                .paint_faded(ui, rect, c.idx, c.fc, ONION_SKIN_ALPHA);
+                        }
+                    }
                 }
             }
             if let Some((x, y, rect)) = hover_xy {
-                self.draw_nib(ui, left_top, rect, x, y, &rendering_canvas);
+                if x < self.canvas.width && y < self.canvas.height {
+                    self.draw_nib(ui, left_top, rect, x, y);
+                }
             }
+            self.draw_selection(ui, left_top, cur_tile_size_vec2, hover_xy);
+            self.draw_text_input(ui, left_top, cur_tile_size_vec2);
         }
 
         use undo::Command;
-        if res.hovered() && hover_pos != None && rect.contains(hover_pos.unwrap()) {
-            let (x, y) = get_grid_x_y(rect, hover_pos.unwrap(), cur_tile_size_vec2);
-            let cell_ref = rendering_canvas.get_cell(x, y);
-            if self.pencil_state.tool == ToolEnum::RectFilled {
+        let layer = self.pencil_state.layer;
+        let locked = self.canvas.layers[layer].locked;
+        let hover_xy = hover_pos
+            .filter(|pos| res.hovered() && rect.contains(*pos))
+            .map(|pos| get_grid_x_y(rect, pos, cur_tile_size_vec2))
+            // a history preview of another size is drawn, but only the document is edited
+            .filter(|&(x, y)| x < self.canvas.width && y < self.canvas.height);
+        if let Some((x, y)) = hover_xy {
+            let cell = *self.canvas.get_cell(layer, x, y);
+            if self.selection.pasting {
+                if res.drag_started() && !locked {
+                    if let Some(cmd) = self.selection.paste_command(layer, x, y) {
+                        self.editing_history.push(&mut self.canvas, cmd);
+                    }
+                }
+            } else if self.pencil_state.tool == ToolEnum::Select {
+                if res.drag_started() {
+                    if self.selection.contains(x, y) && !locked {
+                        self.selection.moving_from = Some((x, y));
+                    } else {
+                        self.selection.select((x, y), (x, y));
+                        self.selection.selecting = true;
+                    }
+                } else if res.drag_released() && self.selection.moving_from.is_some() {
+                    if let Some(cmd) = self.selection.move_command(layer, x, y) {
+                        self.editing_history.push(&mut self.canvas, cmd);
+                    }
+                } else if res.dragged() && self.selection.selecting {
+                    self.selection.drag_to((x, y));
+                }
+            } else if self.pencil_state.tool == ToolEnum::Text {
+                if res.drag_started() && !locked {
+                    self.commit_text_input();
+                    self.text_input.start(layer, x, y, &self.canvas);
+                }
+            } else if self.pencil_state.tool.is_shape() {
                 if res.drag_started() {
                     self.pencil_state.start_xy = Some((x, y));
                 } else if res.drag_released() {
-                    self.pencil_state.to_xy = Some((x, y));
-                    self.editing_history.push(Command::new(
-                        x,
-                        y,
-                        &self.pencil_state,
-                        cell_ref,
-                        false,
-                        rendering_canvas,
-                    ));
+                    if let Some(start_xy) = self.pencil_state.start_xy {
+                        self.pencil_state.to_xy = Some(self.shape_to_xy(ui, start_xy, (x, y)));
+                    }
+                    let cmd =
+                        Command::new(x, y, &self.pencil_state.pen(), &cell, false, &self.canvas);
+                    self.editing_history.push(&mut self.canvas, cmd);
                     self.pencil_state.start_xy = None;
                     self.pencil_state.to_xy = None;
                 }
             } else {
-                let ctx = ui.ctx();
-                if ctx.input(|i| i.pointer.primary_down()) {
-                    self.editing_history.push(Command::new(
-                        x,
-                        y,
-                        &self.pencil_state,
-                        cell_ref,
-                        false,
-                        rendering_canvas,
-                    ));
-                } else if ctx.input(|i| i.pointer.secondary_down()) {
-                    self.editing_history.push(Command::new(
-                        x,
-                        y,
-                        &self.pencil_state,
-                        cell_ref,
-                        true,
-                        rendering_canvas,
-                    ));
+                let (primary, secondary) = ui
+                    .ctx()
+                    .input(|i| (i.pointer.primary_down(), i.pointer.secondary_down()));
+                if primary || secondary {
+                    let pen = self.pencil_state.pen();
+                    self.editing_history.begin_group();
+                    // fast strokes skip cells between two frames, the pencil and eraser join them up
+                    let cells = match self.stroke_from {
+                        Some(from) if matches!(pen.tool, ToolEnum::Pencil | ToolEnum::Eraser) => {
+                            shape::line(from, (x, y)).into_iter().skip(1).collect()
+                        }
+                        _ => vec![(x, y)],
+                    };
+                    for (x, y) in cells {
+                        let cell = self.canvas.get_cell(layer, x, y);
+                        let cmd = Command::new(x, y, &pen, cell, !primary, &self.canvas);
+                        self.editing_history.push(&mut self.canvas, cmd);
+                    }
+                    self.stroke_from = Some((x, y));
+                }
+            }
+        }
+        if !res.dragged() {
+            self.selection.selecting = false;
+            self.selection.moving_from = None;
+            self.stroke_from = None;
+            self.editing_history.end_group();
+        }
+    }
+
+    fn paint_clip(
+        &self,
+        ui: &mut egui::Ui,
+        left_top: egui::Pos2,
+        tile_size: egui::Vec2,
+        clip: &canvas::Clip,
+        (x, y): (usize, usize),
+    ) {
+        for cy in 0..clip.height {
+            for cx in 0..clip.width {
+                if x + cx >= self.canvas.width || y + cy >= self.canvas.height {
+                    continue;
+                }
+                if let Some(c) = clip.cells[cy * clip.width + cx] {
+                    let rect = compute_grid_rect(left_top, tile_size, x + cx, y + cy);
+                    self.tile_tex
+                        .paint_in_rect(ui, rect, c.idx, c.fc, Some(c.bc));
+                }
+            }
+        }
+    }
+
+    fn draw_selection(
+        &self,
+        ui: &mut egui::Ui,
+        left_top: egui::Pos2,
+        tile_size: egui::Vec2,
+        hover_xy: Option<(usize, usize, egui::Rect)>,
+    ) {
+        let stroke = ui.visuals().selection.stroke;
+        let marquee = |ui: &mut egui::Ui, (start_x, start_y): (usize, usize), w, h| {
+            let min = compute_grid_rect(left_top, tile_size, start_x, start_y).min;
+            let rect = egui::Rect::from_min_size(
+                min,
+                egui::vec2(tile_size.x * w as f32, tile_size.y * h as f32),
+            );
+            ui.painter()
+                .rect_stroke(rect, egui::Rounding::none(), stroke);
+        };
+        if let Some((start_x, start_y, to_x, to_y)) = self.selection.rect() {
+            let (w, h) = (to_x - start_x + 1, to_y - start_y + 1);
+            let moved = hover_xy.and_then(|(x, y, _)| self.selection.moved_xy(x, y));
+            if let Some(moved) = moved {
+                let clip =
+                    self.canvas
+                        .copy_region(self.pencil_state.layer, start_x, start_y, to_x, to_y);
+                self.paint_clip(ui, left_top, tile_size, &clip, moved);
+                marquee(ui, moved, w, h);
+            } else {
+                marquee(ui, (start_x, start_y), w, h);
+            }
+        }
+        if let (true, Some(clip), Some((x, y, _))) =
+            (self.selection.pasting, &self.selection.clipboard, hover_xy)
+        {
+            self.paint_clip(ui, left_top, tile_size, clip, (x, y));
+            marquee(ui, (x, y), clip.width, clip.height);
+        }
+    }
+
+    fn draw_text_input(&self, ui: &mut egui::Ui, left_top: egui::Pos2, tile_size: egui::Vec2) {
+        for &(x, y, c) in self.text_input.cells() {
+            let rect = compute_grid_rect(left_top, tile_size, x, y);
+            self.tile_tex
+                .paint_in_rect(ui, rect, c.idx, c.fc, Some(c.bc));
+        }
+        if let Some((x, y)) = self.text_input.cursor() {
+            let rect = compute_grid_rect(left_top, tile_size, x, y);
+            ui.painter()
+                .rect_stroke(rect, egui::Rounding::none(), ui.visuals().selection.stroke);
+        }
+    }
+
+    fn commit_text_input(&mut self) {
+        if let Some(cmd) = self.text_input.commit(&self.canvas) {
+            self.editing_history.push(&mut self.canvas, cmd);
+        }
+    }
+
+    fn text_input_keys(&mut self, ctx: &egui::Context) {
+        use egui::{Event, Key, Modifiers};
+        if self.pencil_state.tool != ToolEnum::Text {
+            self.commit_text_input();
+        }
+        // what was typed points at cells and a layer of the canvas as it was before
+        if !self.text_input.fits(&self.canvas) {
+            self.text_input.cancel();
+        }
+        if !self.text_input.is_active() || ctx.wants_keyboard_input() {
+            return;
+        }
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        let pencil = &self.pencil_state;
+        for event in ctx.input(|i| i.events.clone()) {
+            match event {
+                Event::Text(text) => {
+                    for idx in text.chars().filter_map(cp437::char_to_idx) {
+                        let tile = canvas::TileState {
+                            idx,
+                            fc: pencil.fc.to_color(),
+                            bc: pencil.bc.to_color(),
+                        };
+                        self.text_input.type_tile(tile, width, height);
+                    }
                 }
+                Event::Key {
+                    key: Key::Backspace,
+                    pressed: true,
+                    ..
+                } => self.text_input.backspace(),
+                Event::Key {
+                    key: Key::Enter,
+                    pressed: true,
+                    ..
+                } => self.text_input.newline(),
+                _ => {}
+            }
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Escape)) {
+            self.commit_text_input();
+        }
+    }
+
+    fn copy_selection(&mut self) {
+        self.selection.copy(&self.canvas, self.pencil_state.layer);
+    }
+
+    fn cut_selection(&mut self) {
+        let layer = self.pencil_state.layer;
+        if !self.canvas.layers[layer].locked {
+            if let Some(cmd) = self.selection.cut_command(&self.canvas, layer) {
+                self.editing_history.push(&mut self.canvas, cmd);
+            }
+        }
+    }
+
+    fn delete_selection(&mut self) {
+        let layer = self.pencil_state.layer;
+        if !self.canvas.layers[layer].locked {
+            if let Some(cmd) = self.selection.clear_command(layer) {
+                self.editing_history.push(&mut self.canvas, cmd);
             }
         }
     }
 
-    fn draw_canvas_in_scroll_area(&mut self, ui: &mut egui::Ui, rendering_canvas: &Canvas) {
+    fn start_pasting(&mut self) {
+        self.selection.pasting = self.selection.clipboard.is_some();
+    }
+
+    fn selection_shortcuts(&mut self, ctx: &egui::Context) {
+        use egui::{Key, Modifiers};
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::C)) {
+            self.copy_selection();
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::X)) {
+            self.cut_selection();
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::V)) {
+            self.start_pasting();
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Delete)) {
+            self.delete_selection();
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Escape)) {
+            if self.selection.pasting {
+                self.selection.pasting = false;
+            } else {
+                self.selection.deselect();
+            }
+        }
+    }
+
+    fn draw_canvas_in_scroll_area(&mut self, ui: &mut egui::Ui) {
         self.cur_cell = None;
 
         let available_size = ui.available_size();
 
         let mut outer_size = egui::vec2(
-            self.rendering_scale * TILE_SIZE * rendering_canvas.width as f32,
-            self.rendering_scale * TILE_SIZE * rendering_canvas.height as f32,
+            self.rendering_scale * self.tile_tex.tile_size.x * self.shown_canvas().width as f32,
+            self.rendering_scale * self.tile_tex.tile_size.y * self.shown_canvas().height as f32,
         );
 
         let render_size = outer_size;
@@ -364,13 +1039,15 @@ impl FakePaint {
         let (_, outer_rect) = ui.allocate_space(outer_size);
         ui.allocate_ui_at_rect(outer_rect, |ui| {
             ui.centered_and_justified(|ui| {
-                self.draw_canvas(ui, rendering_canvas, render_size);
+                self.draw_canvas(ui, render_size);
             });
         });
     }
 
     fn draw_palette(&mut self, ui: &mut egui::Ui) {
-        self.pencil_state.draw_palette(ui);
+        if let Some(e) = self.pencil_state.draw_palette(ui) {
+            self.toasts.error(e);
+        }
     }
 
     fn draw_pencil_colors(&mut self, ui: &mut egui::Ui) {
@@ -413,20 +1090,144 @@ impl FakePaint {
                 });
             ui.horizontal(|ui| {
                 if ui.button(t!("undo")).clicked() {
-                    self.editing_history.undo();
+                    self.commit_text_input();
+                    self.editing_history.undo(&mut self.canvas);
+                    self.fit_layer();
                 }
                 if ui.button(t!("redo")).clicked() {
-                    self.editing_history.redo();
+                    self.commit_text_input();
+                    self.editing_history.redo(&mut self.canvas);
+                    self.fit_layer();
                 }
             });
         });
     }
 
+    fn draw_box_drawing_option(&mut self, ui: &mut egui::Ui) {
+        use shape::BoxDrawing;
+        ui.label(t!("box_drawing"));
+        ui.horizontal(|ui| {
+            let box_drawing = &mut self.pencil_state.box_drawing;
+            ui.selectable_value(box_drawing, BoxDrawing::Off, "×");
+            ui.selectable_value(box_drawing, BoxDrawing::Single, "─");
+            ui.selectable_value(box_drawing, BoxDrawing::Double, "═");
+        });
+    }
+
+    fn draw_layers(&mut self, ui: &mut egui::Ui) {
+        use undo::Command;
+        ui.heading(t!("layer"));
+        let layer_count = self.canvas.layers.len();
+        let cur = self.pencil_state.layer;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(layer_count < canvas::MAX_LAYERS, egui::Button::new("＋"))
+                .clicked()
+            {
+                self.commit_text_input();
+                self.editing_history.push(
+                    &mut self.canvas,
+                    Command::AddLayer {
+                        idx: cur + 1,
+                        name: canvas::default_layer_name(layer_count),
+                    },
+                );
+                self.pencil_state.layer = cur + 1;
+            }
+            if ui
+                .add_enabled(layer_count > 1, egui::Button::new("－"))
+                .clicked()
+            {
+                self.commit_text_input();
+                self.editing_history
+                    .push(&mut self.canvas, Command::RemoveLayer { idx: cur });
+                self.pencil_state.layer = cur.saturating_sub(1);
+            }
+            if ui
+                .add_enabled(cur + 1 < layer_count, egui::Button::new("⭡"))
+                .clicked()
+            {
+                self.commit_text_input();
+                self.editing_history.push(
+                    &mut self.canvas,
+                    Command::MoveLayer {
+                        from: cur,
+                        to: cur + 1,
+                    },
+                );
+                self.pencil_state.layer = cur + 1;
+            }
+            if ui.add_enabled(cur > 0, egui::Button::new("⭣")).clicked() {
+                self.commit_text_input();
+                self.editing_history.push(
+                    &mut self.canvas,
+                    Command::MoveLayer {
+                        from: cur,
+                        to: cur - 1,
+                    },
+                );
+                self.pencil_state.layer = cur - 1;
+            }
+        });
+        // the buttons above may have changed the layers
+        for idx in (0..self.canvas.layers.len()).rev() {
+            let layer = &self.canvas.layers[idx];
+            let (name, mut visible, mut locked) = (layer.name.clone(), layer.visible, layer.locked);
+            ui.horizontal(|ui| {
+                if ui
+                    .checkbox(&mut visible, "")
+                    .on_hover_text(t!("visible"))
+                    .changed()
+                {
+                    self.editing_history.push(
+                        &mut self.canvas,
+                        Command::SetLayerVisible {
+                            layer: idx,
+                            visible,
+                        },
+                    );
+                }
+                if ui
+                    .toggle_value(&mut locked, "🔒")
+                    .on_hover_text(t!("lock"))
+                    .changed()
+                {
+                    self.editing_history.push(
+                        &mut self.canvas,
+                        Command::SetLayerLocked { layer: idx, locked },
+                    );
+                }
+                let res = ui.selectable_label(idx == cur, &name);
+                if res.clicked() {
+                    self.pencil_state.layer = idx;
+                }
+                res.context_menu(|ui| {
+                    if ui
+                        .text_edit_singleline(&mut self.renaming_layer)
+                        .gained_focus()
+                    {
+                        self.renaming_layer = name.clone();
+                    }
+                    if ui.button(t!("rename")).clicked() {
+                        self.editing_history.push(
+                            &mut self.canvas,
+                            Command::RenameLayer {
+                                layer: idx,
+                                name: self.renaming_layer.clone(),
+                            },
+                        );
+                        ui.close_menu();
+                    }
+                });
+            });
+        }
+    }
+
     fn char_preview(&self, idx: usize, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.add(
-                self.tile
-                    .to_image(idx, egui::Vec2::splat(TILE_SIZE * 1.5))
+                self.tile_tex
+                    .to_image(idx, self.tile_tex.preview_size(TILE_SIZE * 1.5))
                     .tint(self.pencil_state.fc)
                     .bg_fill(self.pencil_state.bc),
             );
@@ -439,7 +1240,7 @@ impl FakePaint {
         });
     }
 
-    fn current_canvas_info(&self, ui: &mut egui::Ui, rendering_canvas: &Canvas) {
+    fn current_canvas_info(&self, ui: &mut egui::Ui) {
         ui.heading(t!("info"));
         egui::Grid::new("info-grid").show(ui, |ui| {
             ui.label(format!("{}: ", t!("file")));
@@ -455,11 +1256,14 @@ impl FakePaint {
                 }
             }
             ui.end_row();
+            ui.label(format!("{}: ", t!("status")));
+            ui.label(match self.is_dirty() {
+                true => t!("unsaved_changes"),
+                false => t!("saved"),
+            });
+            ui.end_row();
             ui.label(format!("{}: ", t!("canvas_size")));
-            ui.label(format!(
-                "{}x{}",
-                rendering_canvas.width, rendering_canvas.height
-            ));
+            ui.label(format!("{}x{}", self.canvas.width, self.canvas.height));
             ui.end_row();
 
             fn draw_none_cell(ui: &mut egui::Ui) {
@@ -483,11 +1287,11 @@ impl FakePaint {
                     ui.label(format!("{}", cell.idx));
                     ui.end_row();
                     ui.label(format!("{}: ", t!("foreground_color")));
-                    let (r, g, b, _) = cell.fc.to_tuple();
+                    let [r, g, b] = cell.fc.to_array();
                     ui.label(format!("({:02X}, {:02X}, {:02X})", r, g, b));
                     ui.end_row();
                     ui.label(format!("{}: ", t!("background_color")));
-                    let (r, g, b, _) = cell.bc.to_tuple();
+                    let [r, g, b] = cell.bc.to_array();
                     ui.label(format!("({:02X}, {:02X}, {:02X})", r, g, b));
                     ui.end_row();
                 } else {
@@ -509,11 +1313,12 @@ impl FakePaint {
         let x = idx % self.tile.columns;
         let y = idx / self.tile.columns;
         ui.heading(format!("{}--({},{})", t!("char"), x, y));
+        let preview_size = self.tile_tex.preview_size(TILE_SIZE);
         egui::Grid::new("char-selectors")
             .spacing(egui::Vec2::ZERO)
             .striped(true)
-            .num_columns(16)
-            .min_col_width(TILE_SIZE)
+            .num_columns(self.tile.columns)
+            .min_col_width(preview_size.x)
             .min_row_height(TILE_SIZE)
             .show(ui, |ui| {
                 let mut idx = 0;
@@ -521,10 +1326,10 @@ impl FakePaint {
                     for _ in 0..self.tile.columns {
                         let res = ui
                             .add(
-                                ImageButton::new(Some(self.tile.tex.id()), TILE_SIZE_VEC2)
+                                ImageButton::new(Some(self.tile_tex.tex.id()), preview_size)
                                     .selected(self.pencil_state.idx == idx)
                                     .frame(false)
-                                    .uv(self.tile.uv(idx))
+                                    .uv(self.tile_tex.uv(idx))
                                     .tint(egui::Color32::DARK_GRAY)
                                     .bg_fill(egui::Color32::TRANSPARENT)
                                     .selected_tint(egui::Color32::WHITE)
@@ -548,80 +1353,180 @@ impl FakePaint {
 }
 
 impl eframe::App for FakePaint {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let rendering_canvas = self.editing_history.excute_on_canvas(&self.canvas);
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let title = self.window_title();
+        if title != self.window_title {
+            frame.set_window_title(&title);
+            self.window_title = title;
+        }
+        self.frame_strip
+            .play(ctx, &mut self.canvas, &mut self.editing_history);
+        self.fit_layer();
+        self.selection.fit(self.canvas.width, self.canvas.height);
+        self.text_input_keys(ctx);
+        self.selection_shortcuts(ctx);
 
         egui::TopBottomPanel::new(egui::panel::TopBottomSide::Top, "top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.menu_button(t!("file"), |ui| {
-                    if ui.button(t!("open")).clicked() {
+                    if ui.button(t!("open")).clicked() && self.confirm_discard() {
                         if let Some(path) = rfd::FileDialog::new()
                             .set_title(t!("select_json"))
                             .add_filter("json", &["json"])
+                            .add_filter("fpz", &["fpz"])
+                            .add_filter("fpb", &["fpb"])
+                            .add_filter("xp", &["xp"])
+                            .pick_file()
+                        {
+                            match load_canvas_from_file(std::path::Path::new(&path)) {
+                                Ok(mut cc) => {
+                                    // the document is checked against the tileset it will be drawn with
+                                    let tileset = cc
+                                        .tileset
+                                        .filter(|info| *info != self.tile.info)
+                                        .and_then(|info| match TileSet::load(&info) {
+                                            Ok(tile) => Some(tile),
+                                            Err(e) => {
+                                                self.toasts.error(format!(
+                                                    "{}: {}",
+                                                    t!("load_failed"),
+                                                    e
+                                                ));
+                                                None
+                                            }
+                                        });
+                                    let glyph_count = tileset
+                                        .as_ref()
+                                        .map_or(self.tile.glyph_count(), |tile| tile.glyph_count());
+                                    if let Some(repaired) =
+                                        self.accept_document(&mut cc.animation, glyph_count)
+                                    {
+                                        self.load_document(cc.animation);
+                                        if repaired {
+                                            self.frame_strip.mark_modified();
+                                        }
+                                        self.metadata = cc.metadata;
+                                        if !cc.palette.is_empty() {
+                                            self.pencil_state.palette = cc
+                                                .palette
+                                                .into_iter()
+                                                .map(|c| c.to_color32())
+                                                .collect::<Vec<_>>()
+                                                .into();
+                                        }
+                                        if let Some(tile) = tileset {
+                                            self.use_tileset(ctx, tile);
+                                        }
+                                        if let Some(string) = path.to_str() {
+                                            self.editing_file_path = Some(string.to_string());
+                                        }
+                                        if !repaired {
+                                            self.restore_history();
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    self.toasts.error(format!("{}: {}", t!("load_failed"), e))
+                                }
+                            }
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("import_ansi")).clicked() && self.confirm_discard() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("ans", &["ans", "txt"])
                             .pick_file()
                         {
-                            if let Ok(cc) = load_canvas_from_file(std::path::Path::new(&path)) {
-                                self.canvas = cc;
-                                if let Some(string) = path.to_str() {
-                                    self.editing_file_path = Some(string.to_string());
-                                    self.editing_history.clear();
+                            match file::import_ansi_from_file(&path) {
+                                Ok(import) => {
+                                    let mut animation = Animation::from(import.canvas);
+                                    let glyph_count = self.tile.glyph_count();
+                                    if let Some(repaired) =
+                                        self.accept_document(&mut animation, glyph_count)
+                                    {
+                                        self.load_document(animation);
+                                        if repaired {
+                                            self.frame_strip.mark_modified();
+                                        }
+                                        self.editing_file_path = None;
+                                        if !import.unsupported.is_empty() {
+                                            rfd::MessageDialog::new()
+                                                .set_title(t!("import_ansi"))
+                                                .set_description(format!(
+                                                    "{}\n{}",
+                                                    t!("unsupported_sequences"),
+                                                    import.unsupported.join(" ")
+                                                ))
+                                                .set_level(rfd::MessageLevel::Warning)
+                                                .show();
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    self.toasts.error(format!("{}: {}", t!("load_failed"), e))
                                 }
                             }
-                            // else {
-                            //     rfd::MessageDialog::new()
-                            //         .set_description("加载错误")
-                            //         .show();
-                            // }
                         }
                         ui.close_menu();
                     }
-                    if ui.button(t!("new")).clicked() {
+                    if ui.button(t!("new")).clicked() && self.confirm_discard() {
                         self.new_file_window.open();
                         ui.close_menu();
                     }
                     if ui.button(t!("save")).clicked() {
-                        self.canvas = self.editing_history.excute_on_canvas(&self.canvas);
-                        self.editing_history.clear();
-                        if let Some(path) = &self.editing_file_path {
-                            let _ = write_canvas_to_file(&self.canvas, &std::path::Path::new(path));
-                        } else {
-                            if let Some(path) = rfd::FileDialog::new()
-                                .set_title(t!("save"))
-                                .add_filter("json", &["json"])
-                                .save_file()
-                            {
-                                let _ = write_canvas_to_file(&self.canvas, &path);
-                                if let Some(string) = path.to_str() {
-                                    self.editing_file_path = Some(string.to_string());
-                                }
-                            }
-                        }
+                        self.save_document(false);
                         ui.close_menu();
                     }
                     if ui.button(t!("save_as")).clicked() {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .set_title(t!("save"))
-                            .add_filter("json", &["json"])
-                            .save_file()
-                        {
-                            self.canvas = self.editing_history.excute_on_canvas(&self.canvas);
-                            self.editing_history.clear();
-                            let _ = write_canvas_to_file(&self.canvas, &path);
-                            if let Some(string) = path.to_str() {
-                                self.editing_file_path = Some(string.to_string());
-                            }
-                        }
+                        self.save_document(true);
                         ui.close_menu();
                     }
                     if ui.button(t!("export")).clicked() {
                         self.export_image_window.open();
                         ui.close_menu();
                     }
+                    if ui.button(t!("export_ansi")).clicked() {
+                        self.export_ansi_window.open();
+                        ui.close_menu();
+                    }
                 });
                 ui.menu_button(t!("edit"), |ui| {
+                    if ui.button(t!("copy")).clicked() {
+                        self.copy_selection();
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("cut")).clicked() {
+                        self.cut_selection();
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("paste")).clicked() {
+                        self.start_pasting();
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("delete")).clicked() {
+                        self.delete_selection();
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("deselect")).clicked() {
+                        self.selection.deselect();
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button(t!("canvas_size")).clicked() {
                         self.canvas_size_window
-                            .open(rendering_canvas.width, rendering_canvas.height);
+                            .open(self.canvas.width, self.canvas.height);
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("tileset")).clicked() {
+                        self.tileset_window.open(&self.tile.info);
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("document_info")).clicked() {
+                        self.metadata_window.open(&self.metadata);
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("history")).clicked() {
+                        self.history_window.open();
                         ui.close_menu();
                     }
                 });
@@ -638,7 +1543,7 @@ impl eframe::App for FakePaint {
                 self.draw_pencil_colors(ui);
             });
             ui.separator();
-            self.current_canvas_info(ui, &rendering_canvas);
+            self.current_canvas_info(ui);
         });
 
         egui::SidePanel::right("right_panel")
@@ -659,6 +1564,32 @@ impl eframe::App for FakePaint {
                     ToolEnum::RectFilled,
                     t!("rect_filled"),
                 );
+                ui.selectable_value(
+                    &mut self.pencil_state.tool,
+                    ToolEnum::RectOutline,
+                    t!("rect_outline"),
+                );
+                ui.selectable_value(
+                    &mut self.pencil_state.tool,
+                    ToolEnum::EllipseFilled,
+                    t!("ellipse_filled"),
+                );
+                ui.selectable_value(
+                    &mut self.pencil_state.tool,
+                    ToolEnum::EllipseOutline,
+                    t!("ellipse_outline"),
+                );
+                ui.selectable_value(&mut self.pencil_state.tool, ToolEnum::Line, t!("line"));
+                ui.selectable_value(&mut self.pencil_state.tool, ToolEnum::Select, t!("select"));
+                ui.selectable_value(&mut self.pencil_state.tool, ToolEnum::Text, t!("text"));
+                if matches!(
+                    self.pencil_state.tool,
+                    ToolEnum::Line | ToolEnum::RectOutline
+                ) {
+                    self.draw_box_drawing_option(ui);
+                }
+                ui.separator();
+                self.draw_layers(ui);
             });
 
         egui::TopBottomPanel::new(egui::panel::TopBottomSide::Bottom, "bottom_panel").show(
@@ -672,25 +1603,96 @@ impl eframe::App for FakePaint {
                 );
             },
         );
+        let frame_action =
+            egui::TopBottomPanel::new(egui::panel::TopBottomSide::Bottom, "frame_panel")
+                .show(ctx, |ui| self.frame_strip.show(ui))
+                .inner;
         egui::CentralPanel::default().show(ctx, |ui| {
-            self.export_image_window
-                .show(ctx, &rendering_canvas, &self.tile);
+            let frame_strip = &mut self.frame_strip;
+            let export_error = self
+                .export_image_window
+                .show(ctx, &self.canvas, &self.tile, || {
+                    frame_strip.animation(&self.canvas)
+                })
+                .or_else(|| self.export_ansi_window.show(ctx, &self.canvas));
+            if let Some(e) = export_error {
+                self.toasts.error(format!("{}: {}", t!("export_failed"), e));
+            }
             if let Some(cmd) = self.canvas_size_window.show(ctx) {
-                self.editing_history.push(cmd);
+                self.commit_text_input();
+                self.editing_history.push(&mut self.canvas, cmd);
+            }
+            if let Some(info) = self.tileset_window.show(ctx) {
+                self.set_tileset(ctx, &info);
+            }
+            if let Some(metadata) = self.metadata_window.show(ctx) {
+                if metadata != self.metadata {
+                    self.metadata = metadata;
+                    self.frame_strip.mark_modified();
+                }
+            }
+            let history_action = self.history_window.show(ctx, &self.editing_history);
+            if let Some(
+                HistoryAction::Jump(_)
+                | HistoryAction::SwitchBranch { .. }
+                | HistoryAction::TravelTo(_),
+            ) = history_action
+            {
+                self.commit_text_input();
+            }
+            match history_action {
+                Some(HistoryAction::Jump(step)) => {
+                    self.editing_history.jump_to(&mut self.canvas, step);
+                    self.history_preview = None;
+                }
+                Some(HistoryAction::Preview(step)) => {
+                    let key = (step, self.editing_history.current());
+                    if self.history_preview.as_ref().map(|(k, _)| *k) != Some(key) {
+                        let preview = self.editing_history.preview(&self.canvas, step);
+                        self.history_preview = Some((key, preview));
+                    }
+                }
+                Some(HistoryAction::SwitchBranch { forward }) => {
+                    self.editing_history
+                        .switch_branch(&mut self.canvas, forward);
+                    self.history_preview = None;
+                }
+                Some(HistoryAction::TravelTo(time)) => {
+                    self.editing_history.travel_to(&mut self.canvas, time);
+                    self.history_preview = None;
+                }
+                None => self.history_preview = None,
             }
+            self.fit_layer();
             if self
                 .new_file_window
                 .show(ctx, &mut self.canvas, &mut self.editing_file_path)
             {
-                self.editing_history.clear();
+                let canvas = std::mem::take(&mut self.canvas);
+                self.load_document(Animation::from(canvas));
             }
             egui::ScrollArea::both()
                 .auto_shrink([false, false])
                 .scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysVisible)
                 .show(ui, |ui| {
-                    self.draw_canvas_in_scroll_area(ui, &rendering_canvas);
+                    self.draw_canvas_in_scroll_area(ui);
                 });
         });
+
+        self.toasts.show(ctx);
+        self.autosave(ctx);
+
+        // switching frames swaps the canvas and history, so it waits until nothing else uses them
+        if let Some(action) = frame_action {
+            self.commit_text_input();
+            self.pencil_state.start_xy = None;
+            self.frame_strip
+                .apply(ctx, action, &mut self.canvas, &mut self.editing_history);
+        }
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        self.confirm_discard()
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -704,10 +1706,14 @@ impl eframe::App for FakePaint {
             storage.set_string("pen", string);
         }
 
-        if let Ok(string) = serde_json::to_string(&self.canvas) {
+        if let Ok(string) = serde_json::to_string(&self.frame_strip.animation(&self.canvas)) {
             storage.set_string("canvas", string);
         }
 
+        if let Ok(string) = serde_json::to_string(&self.metadata) {
+            storage.set_string("metadata", string);
+        }
+
         if let Ok(string) = serde_json::to_string(self.pencil_state.palette_vec_ref()) {
             storage.set_string("palette", string);
         }
@@ -715,5 +1721,9 @@ impl eframe::App for FakePaint {
         if let Ok(string) = serde_json::to_string(&self.editing_file_path) {
             storage.set_string("editing_file_path", string);
         }
+
+        if let Ok(string) = serde_json::to_string(&self.tile.info) {
+            storage.set_string("tileset", string);
+        }
     }
 }
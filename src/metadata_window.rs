@@ -0,0 +1,50 @@
+use eframe::egui;
+
+use fakepaint::file::Metadata;
+
+#[derive(Default)]
+pub struct MetadataWindow {
+    open: bool,
+    pub metadata: Metadata,
+}
+
+impl MetadataWindow {
+    pub fn open(&mut self, metadata: &Metadata) {
+        self.open = true;
+        self.metadata = metadata.clone();
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Metadata> {
+        use rust_i18n::t;
+        let mut applied = None;
+        egui::Window::new(t!("document_info"))
+            .resizable(false)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                egui::Grid::new("metadata")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(t!("title"));
+                        ui.text_edit_singleline(&mut self.metadata.title);
+                        ui.end_row();
+                        ui.label(t!("author"));
+                        ui.text_edit_singleline(&mut self.metadata.author);
+                        ui.end_row();
+                        // fields written by other tools are kept but not edited here
+                        for (key, value) in &self.metadata.extra {
+                            ui.label(key);
+                            ui.label(value.to_string());
+                            ui.end_row();
+                        }
+                        if ui.button(t!("ok")).clicked() {
+                            applied = Some(self.metadata.clone());
+                        }
+                    });
+            });
+        if applied.is_some() {
+            self.open = false;
+        }
+        applied
+    }
+}
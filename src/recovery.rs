@@ -0,0 +1,102 @@
+//! copies of unsaved edits written in the background, so a crash does not lose them
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::file::{with_path, write_canvas_to_file, CanvasFileRef, FileError};
+
+/// where the unsaved edits of `document` are kept, a hidden file next to it,
+/// or in the app data directory when it was never saved
+pub fn recovery_path(document: Option<&Path>) -> Option<PathBuf> {
+    match document {
+        Some(path) => {
+            let mut name = OsString::from(".");
+            name.push(path.file_name()?);
+            name.push(".recovery.fpz");
+            Some(path.with_file_name(name))
+        }
+        None => directories_next::ProjectDirs::from("", "", "fakepaint")
+            .map(|dirs| dirs.data_dir().join("untitled.recovery.fpz")),
+    }
+}
+
+/// writes the document to `path`, through a temporary file so a crash while writing keeps the last copy
+pub fn write_recovery(document: &CanvasFileRef, path: &Path) -> Result<(), FileError> {
+    if let Some(dir) = path.parent() {
+        with_path(dir, || Ok(std::fs::create_dir_all(dir)?))?;
+    }
+    let temp = path.with_extension("tmp.fpz");
+    write_canvas_to_file(document, &temp)?;
+    with_path(path, || Ok(std::fs::rename(&temp, path)?))
+}
+
+/// the recovery file of `document` when it was written after the document was last saved
+pub fn find_recovery(document: Option<&Path>) -> Option<PathBuf> {
+    let recovery = recovery_path(document)?;
+    let recovered_at = std::fs::metadata(&recovery)
+        .and_then(|m| m.modified())
+        .ok()?;
+    let saved_at =
+        document.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+    match saved_at {
+        Some(saved_at) if saved_at >= recovered_at => None,
+        _ => Some(recovery),
+    }
+}
+
+/// forgets the unsaved edits of `document`, once they are saved or discarded
+pub fn remove_recovery(document: Option<&Path>) {
+    if let Some(path) = recovery_path(document) {
+        // most of the time there is nothing to remove
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::animation::Animation;
+    use crate::canvas::Canvas;
+    use crate::file::Metadata;
+    use crate::tile::TileSetInfo;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_recovery() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            recovery_path(Some(Path::new("maps/town.json"))),
+            Some(PathBuf::from("maps/.town.json.recovery.fpz"))
+        );
+
+        let dir = std::env::temp_dir().join(format!("fakepaint-recovery-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let document = dir.join("town.json");
+        let animation = Animation::from(Canvas::with_size(2, 2));
+        let u = CanvasFileRef {
+            tileset: &TileSetInfo::default(),
+            palette: &[],
+            metadata: &Metadata::default(),
+            animation: &animation,
+        };
+        write_canvas_to_file(&u, &document)?;
+        assert_eq!(find_recovery(Some(&document)), None);
+
+        let recovery = recovery_path(Some(&document)).unwrap();
+        write_recovery(&u, &recovery)?;
+        let set_saved_at = |time: SystemTime| {
+            std::fs::File::options()
+                .write(true)
+                .open(&document)?
+                .set_modified(time)
+        };
+        set_saved_at(SystemTime::now() - Duration::from_secs(60))?;
+        assert_eq!(find_recovery(Some(&document)), Some(recovery.clone()));
+        set_saved_at(SystemTime::now() + Duration::from_secs(60))?;
+        assert_eq!(find_recovery(Some(&document)), None);
+
+        remove_recovery(Some(&document));
+        assert!(!recovery.exists());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::canvas::{Canvas, Clip};
+use crate::undo::Command;
+
+/// a cell rectangle on the canvas and the in-app clipboard
+#[derive(Default)]
+pub struct Selection {
+    start_xy: Option<(usize, usize)>,
+    to_xy: Option<(usize, usize)>,
+    pub clipboard: Option<Clip>,
+    pub pasting: bool,
+    pub selecting: bool,
+    pub moving_from: Option<(usize, usize)>,
+}
+
+impl Selection {
+    /// the selected rectangle as (start_x, start_y, to_x, to_y)
+    pub fn rect(&self) -> Option<(usize, usize, usize, usize)> {
+        let (start_x, start_y) = self.start_xy?;
+        let (to_x, to_y) = self.to_xy?;
+        Some((
+            std::cmp::min(start_x, to_x),
+            std::cmp::min(start_y, to_y),
+            std::cmp::max(start_x, to_x),
+            std::cmp::max(start_y, to_y),
+        ))
+    }
+
+    pub fn select(&mut self, start_xy: (usize, usize), to_xy: (usize, usize)) {
+        self.start_xy = Some(start_xy);
+        self.to_xy = Some(to_xy);
+    }
+
+    pub fn drag_to(&mut self, to_xy: (usize, usize)) {
+        self.to_xy = Some(to_xy);
+    }
+
+    pub fn deselect(&mut self) {
+        self.start_xy = None;
+        self.to_xy = None;
+        self.selecting = false;
+        self.moving_from = None;
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        if let Some((start_x, start_y, to_x, to_y)) = self.rect() {
+            (start_x..=to_x).contains(&x) && (start_y..=to_y).contains(&y)
+        } else {
+            false
+        }
+    }
+
+    /// drops or shrinks the selection after the canvas changed its size
+    pub fn fit(&mut self, width: usize, height: usize) {
+        if let Some((start_x, start_y, to_x, to_y)) = self.rect() {
+            if start_x >= width || start_y >= height {
+                self.deselect();
+            } else {
+                self.select(
+                    (start_x, start_y),
+                    (
+                        std::cmp::min(to_x, width - 1),
+                        std::cmp::min(to_y, height - 1),
+                    ),
+                );
+            }
+        }
+    }
+
+    pub fn copy(&mut self, canvas: &Canvas, layer: usize) -> bool {
+        if let Some((start_x, start_y, to_x, to_y)) = self.rect() {
+            self.clipboard = Some(canvas.copy_region(layer, start_x, start_y, to_x, to_y));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn clear_command(&self, layer: usize) -> Option<Command> {
+        let (start_x, start_y, to_x, to_y) = self.rect()?;
+        Some(Command::Clear {
+            layer,
+            start_x,
+            start_y,
+            to_x,
+            to_y,
+        })
+    }
+
+    pub fn cut_command(&mut self, canvas: &Canvas, layer: usize) -> Option<Command> {
+        if self.copy(canvas, layer) {
+            self.clear_command(layer)
+        } else {
+            None
+        }
+    }
+
+    /// the top left cell of the selection while it is dragged to (x, y)
+    pub fn moved_xy(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (start_x, start_y, _, _) = self.rect()?;
+        let (from_x, from_y) = self.moving_from?;
+        Some((
+            (start_x + x).saturating_sub(from_x),
+            (start_y + y).saturating_sub(from_y),
+        ))
+    }
+
+    /// finishes moving the selection and selects the region at its new place
+    pub fn move_command(&mut self, layer: usize, x: usize, y: usize) -> Option<Command> {
+        let (start_x, start_y, to_x, to_y) = self.rect()?;
+        let (new_x, new_y) = self.moved_xy(x, y)?;
+        self.moving_from = None;
+        if (new_x, new_y) == (start_x, start_y) {
+            return None;
+        }
+        self.select(
+            (new_x, new_y),
+            (new_x + to_x - start_x, new_y + to_y - start_y),
+        );
+        Some(Command::MoveRegion {
+            layer,
+            start_x,
+            start_y,
+            to_x,
+            to_y,
+            x: new_x,
+            y: new_y,
+        })
+    }
+
+    /// drops the floating clipboard at (x, y) and selects the pasted region
+    pub fn paste_command(&mut self, layer: usize, x: usize, y: usize) -> Option<Command> {
+        let clip = self.clipboard.clone()?;
+        self.pasting = false;
+        self.select((x, y), (x + clip.width - 1, y + clip.height - 1));
+        Some(Command::Paste { layer, x, y, clip })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_move_command() {
+        let mut selection = Selection::default();
+        selection.select((3, 2), (1, 1));
+        assert_eq!(selection.rect(), Some((1, 1, 3, 2)));
+        selection.moving_from = Some((2, 2));
+        assert_eq!(selection.moved_xy(0, 5), Some((0, 4)));
+        assert_eq!(
+            selection.move_command(0, 4, 3),
+            Some(Command::MoveRegion {
+                layer: 0,
+                start_x: 1,
+                start_y: 1,
+                to_x: 3,
+                to_y: 2,
+                x: 3,
+                y: 2,
+            })
+        );
+        assert_eq!(selection.rect(), Some((3, 2, 5, 3)));
+        selection.fit(5, 5);
+        assert_eq!(selection.rect(), Some((3, 2, 4, 3)));
+    }
+
+    #[test]
+    fn test_cut_and_paste() {
+        let mut canvas = Canvas::with_size(4, 4);
+        let tile = Some(crate::canvas::TileState {
+            idx: 1,
+            fc: crate::color::Color::WHITE,
+            bc: crate::color::Color::BLACK,
+        });
+        *canvas.get_cell_mut(0, 1, 1) = tile;
+        let mut selection = Selection::default();
+        selection.select((1, 1), (2, 1));
+        assert!(selection.cut_command(&canvas, 0).is_some());
+        let clip = selection.clipboard.clone().unwrap();
+        assert_eq!((clip.width, clip.height), (2, 1));
+        let paste = selection.paste_command(0, 3, 3).unwrap();
+        assert!(!selection.pasting);
+        // the part of the clip past the canvas edge is dropped
+        crate::undo::History::new().push(&mut canvas, paste);
+        assert_eq!(canvas.get_cell(0, 3, 3), &tile);
+    }
+}
@@ -0,0 +1,216 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxDrawing {
+    Off,
+    Single,
+    Double,
+}
+
+// CP437 glyphs used by the box drawing mode
+const HORIZONTAL: [usize; 2] = [196, 205];
+const VERTICAL: [usize; 2] = [179, 186];
+// left top, right top, left bottom, right bottom
+const CORNERS: [[usize; 4]; 2] = [[218, 191, 192, 217], [201, 187, 200, 188]];
+const SLASH: usize = b'/' as usize;
+const BACKSLASH: usize = b'\\' as usize;
+
+impl BoxDrawing {
+    fn style_idx(self) -> Option<usize> {
+        match self {
+            BoxDrawing::Off => None,
+            BoxDrawing::Single => Some(0),
+            BoxDrawing::Double => Some(1),
+        }
+    }
+
+    /// picks a glyph for a line from its overall direction
+    pub fn line_glyph(self, start: (usize, usize), to: (usize, usize)) -> Option<usize> {
+        let style = self.style_idx()?;
+        let dx = to.0 as isize - start.0 as isize;
+        let dy = to.1 as isize - start.1 as isize;
+        if dy.abs() * 2 < dx.abs() || (dx == 0 && dy == 0) {
+            Some(HORIZONTAL[style])
+        } else if dx.abs() * 2 < dy.abs() {
+            Some(VERTICAL[style])
+        } else if (dx > 0) == (dy > 0) {
+            Some(BACKSLASH)
+        } else {
+            Some(SLASH)
+        }
+    }
+
+    /// picks the border glyph of the cell (x, y) on a rectangle outline
+    pub fn rect_glyph(
+        self,
+        (x, y): (usize, usize),
+        (start_x, start_y): (usize, usize),
+        (to_x, to_y): (usize, usize),
+    ) -> Option<usize> {
+        let style = self.style_idx()?;
+        let left_or_right = x == start_x || x == to_x;
+        let top_or_bottom = y == start_y || y == to_y;
+        if start_y == to_y || (top_or_bottom && !left_or_right) {
+            Some(HORIZONTAL[style])
+        } else if start_x == to_x || !top_or_bottom {
+            Some(VERTICAL[style])
+        } else {
+            let corner = (x == to_x) as usize + 2 * (y == to_y) as usize;
+            Some(CORNERS[style][corner])
+        }
+    }
+}
+
+/// Bresenham rasterisation of the line from `start` to `to`, both ends included
+pub fn line(start: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (mut x, mut y) = (start.0 as isize, start.1 as isize);
+    let (to_x, to_y) = (to.0 as isize, to.1 as isize);
+    let dx = (to_x - x).abs();
+    let dy = -(to_y - y).abs();
+    let sx = if x < to_x { 1 } else { -1 };
+    let sy = if y < to_y { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut retval = Vec::with_capacity(std::cmp::max(dx, -dy) as usize + 1);
+    loop {
+        retval.push((x as usize, y as usize));
+        if x == to_x && y == to_y {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+    retval
+}
+
+/// cells whose centers lie inside the ellipse fitting the bounding box,
+/// an outline keeps only the cells next to the outside
+pub fn ellipse(
+    (start_x, start_y): (usize, usize),
+    (to_x, to_y): (usize, usize),
+    filled: bool,
+) -> Vec<(usize, usize)> {
+    let cx = (start_x + to_x) as f32 / 2.0;
+    let cy = (start_y + to_y) as f32 / 2.0;
+    let rx = (to_x - start_x) as f32 / 2.0 + 0.5;
+    let ry = (to_y - start_y) as f32 / 2.0 + 0.5;
+    let inside = |x: isize, y: isize| {
+        let dx = (x as f32 - cx) / rx;
+        let dy = (y as f32 - cy) / ry;
+        dx * dx + dy * dy <= 1.0
+    };
+    let mut retval = Vec::new();
+    for y in start_y as isize..=to_y as isize {
+        for x in start_x as isize..=to_x as isize {
+            if inside(x, y)
+                && (filled
+                    || !inside(x - 1, y)
+                    || !inside(x + 1, y)
+                    || !inside(x, y - 1)
+                    || !inside(x, y + 1))
+            {
+                retval.push((x as usize, y as usize));
+            }
+        }
+    }
+    retval
+}
+
+/// moves `to` so that the box from `start` is a square inside the canvas
+pub fn constrain_square(
+    (start_x, start_y): (usize, usize),
+    (to_x, to_y): (usize, usize),
+    width: usize,
+    height: usize,
+) -> (usize, usize) {
+    let limit_x = if to_x < start_x {
+        start_x
+    } else {
+        width - 1 - start_x
+    };
+    let limit_y = if to_y < start_y {
+        start_y
+    } else {
+        height - 1 - start_y
+    };
+    let d = std::cmp::max(to_x.abs_diff(start_x), to_y.abs_diff(start_y));
+    let d = std::cmp::min(d, std::cmp::min(limit_x, limit_y));
+    (
+        if to_x < start_x {
+            start_x - d
+        } else {
+            start_x + d
+        },
+        if to_y < start_y {
+            start_y - d
+        } else {
+            start_y + d
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        assert_eq!(line((0, 0), (3, 0)), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line((2, 2), (0, 0)), vec![(2, 2), (1, 1), (0, 0)]);
+        assert_eq!(
+            line((0, 0), (4, 2)),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+        assert_eq!(line((1, 1), (1, 1)), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn test_line_glyph() {
+        let single = BoxDrawing::Single;
+        assert_eq!(single.line_glyph((0, 0), (5, 1)), Some(196));
+        assert_eq!(single.line_glyph((0, 0), (1, 5)), Some(179));
+        assert_eq!(single.line_glyph((0, 0), (3, 3)), Some(BACKSLASH));
+        assert_eq!(single.line_glyph((0, 3), (3, 0)), Some(SLASH));
+        assert_eq!(BoxDrawing::Double.line_glyph((0, 0), (0, 4)), Some(186));
+        assert_eq!(BoxDrawing::Off.line_glyph((0, 0), (0, 4)), None);
+    }
+
+    #[test]
+    fn test_rect_glyph() {
+        let single = BoxDrawing::Single;
+        assert_eq!(single.rect_glyph((0, 0), (0, 0), (3, 2)), Some(218));
+        assert_eq!(single.rect_glyph((3, 0), (0, 0), (3, 2)), Some(191));
+        assert_eq!(single.rect_glyph((0, 2), (0, 0), (3, 2)), Some(192));
+        assert_eq!(single.rect_glyph((3, 2), (0, 0), (3, 2)), Some(217));
+        assert_eq!(single.rect_glyph((1, 0), (0, 0), (3, 2)), Some(196));
+        assert_eq!(single.rect_glyph((0, 1), (0, 0), (3, 2)), Some(179));
+        assert_eq!(
+            BoxDrawing::Double.rect_glyph((3, 2), (0, 0), (3, 2)),
+            Some(188)
+        );
+        assert_eq!(single.rect_glyph((0, 1), (0, 1), (3, 1)), Some(196));
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let filled = ellipse((0, 0), (4, 4), true);
+        assert_eq!(filled.len(), 21);
+        assert!(!filled.contains(&(0, 0)));
+        assert!(filled.contains(&(2, 2)));
+        let outline = ellipse((0, 0), (4, 4), false);
+        assert_eq!(outline.len(), 12);
+        assert!(!outline.contains(&(2, 2)));
+        assert_eq!(ellipse((1, 1), (1, 1), false), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn test_constrain_square() {
+        assert_eq!(constrain_square((2, 2), (5, 3), 10, 10), (5, 5));
+        assert_eq!(constrain_square((2, 2), (0, 5), 10, 10), (0, 4));
+        assert_eq!(constrain_square((2, 2), (9, 3), 10, 5), (4, 4));
+    }
+}
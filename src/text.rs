@@ -0,0 +1,159 @@
+use crate::canvas::{Canvas, TileState};
+use crate::undo::Command;
+
+/// the run of characters typed with the text tool before it is committed
+#[derive(Default)]
+pub struct TextInput {
+    layer: usize,
+    origin: Option<(usize, usize)>,
+    cursor: (usize, usize),
+    cells: Vec<(usize, usize, TileState)>,
+    /// the width, height and layer count of the canvas typing started on
+    canvas: (usize, usize, usize),
+}
+
+fn shape(canvas: &Canvas) -> (usize, usize, usize) {
+    (canvas.width, canvas.height, canvas.layers.len())
+}
+
+impl TextInput {
+    pub fn start(&mut self, layer: usize, x: usize, y: usize, canvas: &Canvas) {
+        self.canvas = shape(canvas);
+        self.layer = layer;
+        self.origin = Some((x, y));
+        self.cursor = (x, y);
+        self.cells.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    /// the cell the next character is typed into
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.origin.map(|_| self.cursor)
+    }
+
+    pub fn cells(&self) -> &[(usize, usize, TileState)] {
+        &self.cells
+    }
+
+    /// places the tile at the cursor, then advances and wraps the cursor
+    pub fn type_tile(&mut self, tile: TileState, width: usize, height: usize) {
+        let (x, y) = self.cursor;
+        if self.origin.is_none() || y >= height {
+            return;
+        }
+        self.cells.push((x, y, tile));
+        if x + 1 < width {
+            self.cursor = (x + 1, y);
+        } else {
+            self.newline();
+        }
+    }
+
+    pub fn newline(&mut self) {
+        if let Some((origin_x, _)) = self.origin {
+            self.cursor = (origin_x, self.cursor.1 + 1);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some((x, y, _)) = self.cells.pop() {
+            self.cursor = (x, y);
+        }
+    }
+
+    /// whether the canvas still has the size and layers typing started on
+    pub fn fits(&self, canvas: &Canvas) -> bool {
+        self.canvas == shape(canvas)
+    }
+
+    /// drops the typed characters
+    pub fn cancel(&mut self) {
+        self.origin = None;
+        self.cells.clear();
+    }
+
+    /// ends typing, the whole run becomes a single command, clipped to `canvas`
+    pub fn commit(&mut self, canvas: &Canvas) -> Option<Command> {
+        self.origin = None;
+        let cells: Vec<_> = std::mem::take(&mut self.cells)
+            .into_iter()
+            .filter(|&(x, y, _)| x < canvas.width && y < canvas.height)
+            .collect();
+        if cells.is_empty() || self.layer >= canvas.layers.len() {
+            None
+        } else {
+            Some(Command::Text {
+                layer: self.layer,
+                cells,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+
+    fn tile(idx: usize) -> TileState {
+        TileState {
+            idx,
+            fc: Color::WHITE,
+            bc: Color::BLACK,
+        }
+    }
+
+    #[test]
+    fn test_type_and_wrap() {
+        let canvas = Canvas::with_size(3, 2);
+        let mut input = TextInput::default();
+        input.start(0, 1, 0, &canvas);
+        for idx in [72, 105, 33] {
+            input.type_tile(tile(idx), 3, 2);
+        }
+        assert_eq!(input.cursor(), Some((2, 1)));
+        assert_eq!(input.cells()[2], (1, 1, tile(33)));
+        input.backspace();
+        assert_eq!(input.cursor(), Some((1, 1)));
+        input.newline();
+        input.type_tile(tile(0), 3, 2);
+        assert_eq!(input.cells().len(), 2);
+        assert_eq!(
+            input.commit(&canvas),
+            Some(Command::Text {
+                layer: 0,
+                cells: vec![(1, 0, tile(72)), (2, 0, tile(105))],
+            })
+        );
+        assert!(!input.is_active());
+    }
+
+    #[test]
+    fn test_commit_on_changed_canvas() {
+        let mut input = TextInput::default();
+        input.start(1, 0, 0, &Canvas::with_size(4, 1));
+        for idx in 0..4 {
+            input.type_tile(tile(idx), 4, 1);
+        }
+        let mut canvas = Canvas::with_size(2, 1);
+        assert!(!input.fits(&canvas));
+        // typed on a layer that is gone
+        assert_eq!(input.commit(&canvas), None);
+
+        input.start(0, 0, 0, &Canvas::with_size(4, 1));
+        for idx in 0..4 {
+            input.type_tile(tile(idx), 4, 1);
+        }
+        canvas.layers.push(canvas.layers[0].clone());
+        assert_eq!(
+            input.commit(&canvas),
+            Some(Command::Text {
+                layer: 0,
+                cells: vec![(0, 0, tile(0)), (1, 0, tile(1))],
+            })
+        );
+    }
+}
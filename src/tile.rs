@@ -1,99 +1,98 @@
-use eframe::egui;
+use std::path::Path;
 
+use serde::{Deserialize, Serialize};
+
+use crate::file::{with_path, FileError, FileErrorKind};
+
+/// the tileset image and its glyph layout, usable without a window
 pub struct TileSet {
     pub image_data: image::DynamicImage,
-    pub tex: egui::TextureHandle,
-    uv: Vec<egui::Rect>,
     pub columns: usize,
     pub rows: usize,
-    pub tile_size: egui::Vec2,
+    pub info: TileSetInfo,
 }
 
-fn load_image_from_path(
-    path: &std::path::Path,
-) -> Result<(egui::ColorImage, image::DynamicImage), image::ImageError> {
-    let image = image::io::Reader::open(path)?.decode()?;
-    let size = [image.width() as _, image.height() as _];
-    let img_buf = image.to_rgba8();
-    let pixels = img_buf.as_flat_samples();
-    Ok((
-        egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()),
-        image,
-    ))
+/// where a tileset image comes from and how its glyphs are laid out
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct TileSetInfo {
+    pub path: String,
+    pub columns: usize,
+    pub rows: usize,
+    pub glyph_width: usize,
+    pub glyph_height: usize,
+}
+
+impl Default for TileSetInfo {
+    fn default() -> Self {
+        Self {
+            path: "assets/16x16_sm_ascii.png".to_string(),
+            columns: 16,
+            rows: 16,
+            glyph_width: 16,
+            glyph_height: 16,
+        }
+    }
 }
 
-pub fn load_texture(ctx: &egui::Context) -> (Option<egui::TextureHandle>, image::DynamicImage) {
-    let mut ascii: Option<egui::TextureHandle> = None;
-    let tex_opt = egui::TextureOptions {
-        magnification: egui::TextureFilter::Nearest,
-        minification: egui::TextureFilter::Nearest,
-    };
-    let (color_image, image) =
-        load_image_from_path(std::path::Path::new("assets/16x16_sm_ascii.png")).unwrap();
-    ascii.get_or_insert_with(|| ctx.load_texture("16x16_sm_ascii", color_image, tex_opt));
-    (ascii, image)
+impl TileSetInfo {
+    pub fn glyph_count(&self) -> usize {
+        self.columns * self.rows
+    }
 }
 
 impl TileSet {
-    pub fn new(
-        image_data: image::DynamicImage,
-        tex: egui::TextureHandle,
-        columns: usize,
-        rows: usize,
-        tile_size: egui::Vec2,
-    ) -> Self {
-        let ux = 1.0 / (columns as f32);
-        let uy = 1.0 / (rows as f32);
-        let mut uv = Vec::with_capacity(columns * rows);
-        for i in 0..rows {
-            for j in 0..columns {
-                let x = ux * j as f32;
-                let y = uy * i as f32;
-                uv.push(egui::Rect::from_min_size(
-                    egui::pos2(x, y),
-                    egui::Vec2::new(ux, uy),
+    pub fn load(info: &TileSetInfo) -> Result<Self, FileError> {
+        with_path(Path::new(&info.path), || {
+            if info.columns == 0
+                || info.rows == 0
+                || info.glyph_width == 0
+                || info.glyph_height == 0
+            {
+                return Err(FileErrorKind::Invalid(
+                    "tileset grid and glyph size must not be zero".to_string(),
                 ));
             }
-        }
+            let image_data = image::io::Reader::open(&info.path)?.decode()?;
+            let (width, height) = (
+                info.columns * info.glyph_width,
+                info.rows * info.glyph_height,
+            );
+            if (image_data.width() as usize) < width || (image_data.height() as usize) < height {
+                return Err(FileErrorKind::Invalid(format!(
+                    "tileset image is {}x{}, smaller than {}x{}",
+                    image_data.width(),
+                    image_data.height(),
+                    width,
+                    height
+                )));
+            }
+            Ok(Self::new(image_data, info.clone()))
+        })
+    }
+
+    pub fn new(image_data: image::DynamicImage, info: TileSetInfo) -> Self {
         Self {
             image_data,
-            tex,
-            uv,
-            columns,
-            rows,
-            tile_size,
+            columns: info.columns,
+            rows: info.rows,
+            info,
         }
     }
 
-    #[allow(unused)]
-    pub fn uv(&self, idx: usize) -> egui::Rect {
-        self.uv[idx]
+    pub fn glyph_count(&self) -> usize {
+        self.columns * self.rows
     }
 
-    #[allow(unused)]
-    pub fn to_image(&self, idx: usize, size: egui::Vec2) -> egui::Image {
-        egui::Image::new(self.tex.id(), size).uv(self.uv(idx))
+    /// the pixel position of the glyph in the tileset image
+    pub fn pixel_pos(&self, idx: usize) -> (u32, u32) {
+        (
+            ((idx % self.columns) * self.info.glyph_width) as u32,
+            ((idx / self.columns) * self.info.glyph_height) as u32,
+        )
     }
 
-    #[allow(unused)]
-    pub fn to_image_xy(&self, idx: usize, sx: f32, sy: f32) -> egui::Image {
-        egui::Image::new(self.tex.id(), egui::vec2(sx, sy)).uv(self.uv(idx))
-    }
-
-    #[allow(unused)]
-    pub fn paint_in_rect(
-        &self,
-        ui: &mut egui::Ui,
-        rect: egui::Rect,
-        idx: usize,
-        tint: egui::Color32,
-        bg: Option<egui::Color32>,
-    ) {
-        if ui.is_rect_visible(rect) {
-            if let Some(bg) = bg {
-                ui.painter().rect_filled(rect, egui::Rounding::none(), bg);
-            }
-            ui.painter().image(self.tex.id(), rect, self.uv(idx), tint);
-        }
+    /// the pixel size of a single glyph
+    pub fn glyph_size(&self) -> (u32, u32) {
+        (self.info.glyph_width as u32, self.info.glyph_height as u32)
     }
 }
@@ -0,0 +1,106 @@
+use eframe::egui;
+use fakepaint::color::Color;
+use fakepaint::tile::TileSet;
+
+use crate::color_ext::ToColor32;
+
+fn to_color_image(image: &image::DynamicImage) -> egui::ColorImage {
+    let size = [image.width() as _, image.height() as _];
+    let img_buf = image.to_rgba8();
+    let pixels = img_buf.as_flat_samples();
+    egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+}
+
+/// the tileset uploaded to the gpu, only needed when drawing in a window
+pub struct TileTexture {
+    pub tex: egui::TextureHandle,
+    pub tile_size: egui::Vec2,
+    uv: Vec<egui::Rect>,
+}
+
+impl TileTexture {
+    pub fn new(ctx: &egui::Context, tile: &TileSet) -> Self {
+        let tex_opt = egui::TextureOptions {
+            magnification: egui::TextureFilter::Nearest,
+            minification: egui::TextureFilter::Nearest,
+        };
+        let tex = ctx.load_texture(&tile.info.path, to_color_image(&tile.image_data), tex_opt);
+        let (glyph_width, glyph_height) = tile.glyph_size();
+        let tile_size = egui::vec2(glyph_width as f32, glyph_height as f32);
+        // the image may be larger than the glyph grid
+        let ux = tile_size.x / tile.image_data.width() as f32;
+        let uy = tile_size.y / tile.image_data.height() as f32;
+        let mut uv = Vec::with_capacity(tile.glyph_count());
+        for i in 0..tile.rows {
+            for j in 0..tile.columns {
+                let x = ux * j as f32;
+                let y = uy * i as f32;
+                uv.push(egui::Rect::from_min_size(
+                    egui::pos2(x, y),
+                    egui::Vec2::new(ux, uy),
+                ));
+            }
+        }
+        Self { tex, tile_size, uv }
+    }
+
+    /// the glyph size scaled to the given height, used by widgets
+    pub fn preview_size(&self, height: f32) -> egui::Vec2 {
+        egui::vec2(height * self.tile_size.x / self.tile_size.y, height)
+    }
+
+    #[allow(unused)]
+    pub fn uv(&self, idx: usize) -> egui::Rect {
+        // undo can bring back glyphs a smaller tileset does not have, they show as the first one
+        self.uv.get(idx).copied().unwrap_or(self.uv[0])
+    }
+
+    #[allow(unused)]
+    pub fn to_image(&self, idx: usize, size: egui::Vec2) -> egui::Image {
+        egui::Image::new(self.tex.id(), size).uv(self.uv(idx))
+    }
+
+    #[allow(unused)]
+    pub fn to_image_xy(&self, idx: usize, sx: f32, sy: f32) -> egui::Image {
+        egui::Image::new(self.tex.id(), egui::vec2(sx, sy)).uv(self.uv(idx))
+    }
+
+    #[allow(unused)]
+    pub fn paint_in_rect(
+        &self,
+        ui: &mut egui::Ui,
+        rect: egui::Rect,
+        idx: usize,
+        tint: Color,
+        bg: Option<Color>,
+    ) {
+        if ui.is_rect_visible(rect) {
+            if let Some(bg) = bg {
+                ui.painter()
+                    .rect_filled(rect, egui::Rounding::none(), bg.to_color32());
+            }
+            ui.painter()
+                .image(self.tex.id(), rect, self.uv(idx), tint.to_color32());
+        }
+    }
+
+    /// paints the glyph see-through, without a background
+    pub fn paint_faded(
+        &self,
+        ui: &mut egui::Ui,
+        rect: egui::Rect,
+        idx: usize,
+        tint: Color,
+        alpha: f32,
+    ) {
+        if ui.is_rect_visible(rect) {
+            let tint = egui::Color32::from_rgba_unmultiplied(
+                tint.r(),
+                tint.g(),
+                tint.b(),
+                (alpha * 255.0) as u8,
+            );
+            ui.painter().image(self.tex.id(), rect, self.uv(idx), tint);
+        }
+    }
+}
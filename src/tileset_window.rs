@@ -0,0 +1,77 @@
+use eframe::egui;
+
+use fakepaint::tile::TileSetInfo;
+
+#[derive(Default)]
+pub struct TileSetWindow {
+    open: bool,
+    pub info: TileSetInfo,
+}
+
+impl TileSetWindow {
+    pub fn open(&mut self, info: &TileSetInfo) {
+        self.open = true;
+        self.info = info.clone();
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<TileSetInfo> {
+        use rust_i18n::t;
+        let mut loaded = None;
+        egui::Window::new(t!("tileset"))
+            .resizable(false)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                egui::Grid::new("tileset")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(t!("file_name"));
+                        ui.text_edit_singleline(&mut self.info.path);
+                        if ui.button(t!("browse")).clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("png", &["png"])
+                                .pick_file()
+                            {
+                                if let Some(string) = path.to_str() {
+                                    self.info.path = string.to_string();
+                                }
+                            }
+                        }
+                        ui.end_row();
+                        ui.label(t!("glyph_size"));
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.info.glyph_width)
+                                    .clamp_range(core::ops::RangeInclusive::new(1, 256)),
+                            );
+                            ui.label("x");
+                            ui.add(
+                                egui::DragValue::new(&mut self.info.glyph_height)
+                                    .clamp_range(core::ops::RangeInclusive::new(1, 256)),
+                            );
+                        });
+                        ui.end_row();
+                        ui.label(t!("grid"));
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.info.columns)
+                                    .clamp_range(core::ops::RangeInclusive::new(1, 256)),
+                            );
+                            ui.label("x");
+                            ui.add(
+                                egui::DragValue::new(&mut self.info.rows)
+                                    .clamp_range(core::ops::RangeInclusive::new(1, 256)),
+                            );
+                        });
+                        ui.end_row();
+                        if ui.button(t!("load")).clicked() && !self.info.path.is_empty() {
+                            loaded = Some(self.info.clone());
+                        }
+                    });
+            });
+        if loaded.is_some() {
+            self.open = false;
+        }
+        loaded
+    }
+}
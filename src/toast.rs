@@ -0,0 +1,56 @@
+use eframe::egui;
+
+/// seconds a notification stays on screen
+const TOAST_DURATION: f64 = 6.0;
+
+struct Toast {
+    text: String,
+    /// when the toast was first drawn, it only starts counting down once it is visible
+    shown_at: Option<f64>,
+}
+
+/// notifications stacked in the corner of the window, they go away by themselves
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    pub fn error(&mut self, text: impl ToString) {
+        self.toasts.push(Toast {
+            text: text.to_string(),
+            shown_at: None,
+        });
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        self.toasts
+            .retain(|toast| !toast.shown_at.is_some_and(|at| now - at >= TOAST_DURATION));
+        if self.toasts.is_empty() {
+            return;
+        }
+        let mut closed = None;
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, toast) in self.toasts.iter_mut().enumerate() {
+                    toast.shown_at.get_or_insert(now);
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(320.0);
+                        ui.horizontal(|ui| {
+                            ui.colored_label(ui.visuals().error_fg_color, &toast.text);
+                            if ui.small_button("×").clicked() {
+                                closed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(i) = closed {
+            self.toasts.remove(i);
+        }
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64(1.0));
+    }
+}
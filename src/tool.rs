@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::TileState;
+use crate::color::Color;
+use crate::shape::BoxDrawing;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Copy, Debug)]
+pub enum ToolEnum {
+    Pencil,
+    Eraser,
+    Fill,
+    Replace,
+    RectFilled,
+    RectOutline,
+    EllipseFilled,
+    EllipseOutline,
+    Line,
+    Select,
+    Text,
+}
+
+impl ToolEnum {
+    /// shape tools are dragged from a start cell to an end cell
+    pub fn is_shape(&self) -> bool {
+        matches!(
+            self,
+            ToolEnum::RectFilled
+                | ToolEnum::RectOutline
+                | ToolEnum::EllipseFilled
+                | ToolEnum::EllipseOutline
+                | ToolEnum::Line
+        )
+    }
+}
+
+/// the tool, glyph and colors commands are built from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pen {
+    pub tool: ToolEnum,
+    pub layer: usize,
+    pub box_drawing: BoxDrawing,
+    pub start_xy: Option<(usize, usize)>,
+    pub to_xy: Option<(usize, usize)>,
+    pub idx: usize,
+    pub fc: Color,
+    pub bc: Color,
+    /// whether painting replaces the foreground color of the cell
+    pub fc_activate: bool,
+    /// whether painting replaces the background color of the cell
+    pub bc_activate: bool,
+}
+
+impl Default for Pen {
+    fn default() -> Self {
+        Self {
+            tool: ToolEnum::Pencil,
+            layer: 0,
+            box_drawing: BoxDrawing::Off,
+            start_xy: None,
+            to_xy: None,
+            idx: 0,
+            fc: Color::WHITE,
+            bc: Color::BLACK,
+            fc_activate: true,
+            bc_activate: true,
+        }
+    }
+}
+
+impl Pen {
+    /// the colors painted onto the cell, None when the tool paints no colors
+    pub fn get_fc_bc(&self, cell: &Option<TileState>) -> Option<(Color, Color)> {
+        if (!self.fc_activate && !self.bc_activate)
+            || self.tool == ToolEnum::Eraser
+            || self.tool == ToolEnum::Select
+            || self.tool == ToolEnum::Text
+        {
+            None
+        } else if let Some(cell) = cell {
+            Some((
+                if self.fc_activate { self.fc } else { cell.fc },
+                if self.bc_activate { self.bc } else { cell.bc },
+            ))
+        } else {
+            Some((self.fc, self.bc))
+        }
+    }
+
+    pub fn to_tile_state(&self, origin_state: &Option<TileState>) -> Option<TileState> {
+        self.get_fc_bc(origin_state).map(|(fc, bc)| TileState {
+            idx: self.idx,
+            fc,
+            bc,
+        })
+    }
+
+    pub fn swapped_tile_state(&self) -> Option<TileState> {
+        Some(TileState {
+            idx: self.idx,
+            fc: self.bc,
+            bc: self.fc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_fc_bc() {
+        let red = Color::from_rgb(255, 0, 0);
+        let cell = Some(TileState {
+            idx: 1,
+            fc: red,
+            bc: red,
+        });
+        let mut pen = Pen {
+            bc_activate: false,
+            ..Default::default()
+        };
+        assert_eq!(pen.get_fc_bc(&None), Some((Color::WHITE, Color::BLACK)));
+        assert_eq!(pen.get_fc_bc(&cell), Some((Color::WHITE, red)));
+        pen.fc_activate = false;
+        assert_eq!(pen.to_tile_state(&cell), None);
+        pen.tool = ToolEnum::Eraser;
+        pen.fc_activate = true;
+        assert_eq!(pen.get_fc_bc(&cell), None);
+        assert_eq!(pen.swapped_tile_state().unwrap().fc, Color::BLACK);
+    }
+}
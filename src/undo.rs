@@ -1,21 +1,8 @@
-#[derive(Clone, Debug)]
-pub struct FillPos {
-    c: Option<TileState>,
-    x: usize,
-    y: usize,
-    cells: Vec<Vec<bool>>,
-}
-
-impl PartialEq for FillPos {
-    fn eq(&self, other: &Self) -> bool {
-        self.c == other.c && self.x == other.x && self.y == other.y
-    }
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     None,
     Point {
+        layer: usize,
         c: Option<TileState>,
         x: usize,
         y: usize,
@@ -28,25 +15,101 @@ pub enum Command {
         to_x: usize,
         to_y: usize,
     },
-    Fill(FillPos),
+    Fill {
+        layer: usize,
+        c: Option<TileState>,
+        x: usize,
+        y: usize,
+    },
     Replace {
+        layer: usize,
         x: usize,
         y: usize,
         replace_with: Option<TileState>,
     },
     Rect {
+        layer: usize,
         c: Option<TileState>,
         filled: bool,
+        box_drawing: BoxDrawing,
+        start_x: usize,
+        start_y: usize,
+        to_x: usize,
+        to_y: usize,
+    },
+    Ellipse {
+        layer: usize,
+        c: Option<TileState>,
+        filled: bool,
+        start_x: usize,
+        start_y: usize,
+        to_x: usize,
+        to_y: usize,
+    },
+    Line {
+        layer: usize,
+        c: Option<TileState>,
+        box_drawing: BoxDrawing,
+        start_x: usize,
+        start_y: usize,
+        to_x: usize,
+        to_y: usize,
+    },
+    Text {
+        layer: usize,
+        cells: Vec<(usize, usize, TileState)>,
+    },
+    Paste {
+        layer: usize,
+        x: usize,
+        y: usize,
+        clip: Clip,
+    },
+    Clear {
+        layer: usize,
+        start_x: usize,
+        start_y: usize,
+        to_x: usize,
+        to_y: usize,
+    },
+    MoveRegion {
+        layer: usize,
         start_x: usize,
         start_y: usize,
         to_x: usize,
         to_y: usize,
+        x: usize,
+        y: usize,
+    },
+    AddLayer {
+        idx: usize,
+        name: String,
+    },
+    RemoveLayer {
+        idx: usize,
+    },
+    MoveLayer {
+        from: usize,
+        to: usize,
+    },
+    RenameLayer {
+        layer: usize,
+        name: String,
+    },
+    SetLayerVisible {
+        layer: usize,
+        visible: bool,
+    },
+    SetLayerLocked {
+        layer: usize,
+        locked: bool,
     },
 }
 
 impl Default for Command {
     fn default() -> Self {
         Self::Point {
+            layer: 0,
             c: None,
             x: 0,
             y: 0,
@@ -54,28 +117,169 @@ impl Default for Command {
     }
 }
 
-const COMMAND_EXCUTE_GAP: usize = 16;
+/// what a command changed, applying it to the canvas gives back the edit that reverts it
+#[derive(Serialize, Deserialize)]
+enum Edit {
+    /// cells of a layer by index, swapped with what the layer holds
+    Cells {
+        layer: usize,
+        cells: Vec<(usize, Option<TileState>)>,
+    },
+    InsertLayer {
+        idx: usize,
+        layer: Layer,
+    },
+    RemoveLayer {
+        idx: usize,
+    },
+    MoveLayer {
+        from: usize,
+        to: usize,
+    },
+    /// the name, visibility and lock of a layer, swapped with the current ones
+    LayerProps {
+        layer: usize,
+        name: String,
+        visible: bool,
+        locked: bool,
+    },
+    /// a whole canvas swapped with the current one, for changes of its size
+    Canvas(Box<Canvas>),
+    /// edits applied one after the other, such as the cells of a pencil stroke
+    Group(Vec<Edit>),
+}
+
+/// what an undo step did, for listing it in the history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Label {
+    /// the state the history started from
+    Start,
+    Point {
+        x: usize,
+        y: usize,
+        erase: bool,
+    },
+    /// a drag that painted `cells` times
+    Stroke {
+        cells: usize,
+    },
+    Fill {
+        x: usize,
+        y: usize,
+    },
+    Replace {
+        x: usize,
+        y: usize,
+    },
+    Rect {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Ellipse {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Line {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Text {
+        chars: usize,
+    },
+    Paste {
+        x: usize,
+        y: usize,
+    },
+    Clear {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Move {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Resize {
+        width: usize,
+        height: usize,
+    },
+    AddLayer {
+        name: String,
+    },
+    RemoveLayer {
+        name: String,
+    },
+    MoveLayer {
+        name: String,
+    },
+    RenameLayer {
+        name: String,
+    },
+    ShowLayer {
+        name: String,
+        visible: bool,
+    },
+    LockLayer {
+        name: String,
+        locked: bool,
+    },
+}
+
+/// an undo step of the tree, the state after a command
+#[derive(Serialize, Deserialize)]
+struct Node {
+    label: Label,
+    /// reverts the step while it is applied, redoes it otherwise
+    edit: Edit,
+    parent: usize,
+    children: Vec<usize>,
+    /// the child redo goes to, the one visited or made last
+    redo_child: Option<usize>,
+    /// when the step was made, in seconds since the unix epoch
+    time: u64,
+}
+
+/// the edits of every command as a tree, undoing then drawing starts a new branch and keeps the old one
+///
+/// the canvas is edited in place, steps are numbered in the order they were made and 0 is the state the
+/// history started from
+#[derive(Serialize, Deserialize)]
 pub struct History {
-    rendering_canvas: Option<Canvas>,
-    edit_history: Vec<Command>,
-    undo_history: Vec<Command>,
-    last_command: usize,
+    nodes: Vec<Node>,
+    /// the step the canvas is at
+    current: usize,
+    /// the step the document was saved at, None once a group grew past that state
+    #[serde(skip)]
+    saved_at: Option<usize>,
+    /// Some while a group is open, with the step collecting its commands once it has one
+    #[serde(skip)]
+    group: Option<Option<usize>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
 }
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    canvas::TileState,
-    color_editer::{PencilState, ToolEnum},
-    Canvas,
+    canvas::{Canvas, Clip, Layer, TileState, MAX_LAYERS},
+    shape::{self, BoxDrawing},
+    tool::{Pen, ToolEnum},
+    validate,
 };
 
-fn compute_contigeous_cell(canvas: &Canvas, x: usize, y: usize) -> Vec<Vec<bool>> {
+fn compute_contigeous_cell(canvas: &Canvas, layer: usize, x: usize, y: usize) -> Vec<Vec<bool>> {
     let mut retval: Vec<Vec<bool>> = Vec::with_capacity(canvas.height);
     for _ in 0..canvas.height {
         let mut row: Vec<bool> = Vec::with_capacity(canvas.width);
         row.resize(canvas.width, false);
         retval.push(row);
     }
-    let &target_tile = canvas.get_cell(x, y);
+    let &target_tile = canvas.get_cell(layer, x, y);
     let mut unchecked: Vec<(usize, usize)> = Vec::with_capacity(canvas.width * canvas.height);
     unchecked.push((x, y));
     let end_x = canvas.width - 1;
@@ -83,16 +287,16 @@ fn compute_contigeous_cell(canvas: &Canvas, x: usize, y: usize) -> Vec<Vec<bool>
     while !unchecked.is_empty() {
         let (x, y) = unchecked.pop().unwrap();
         retval[y][x] = true;
-        if x > 0 && !retval[y][x - 1] && target_tile == *canvas.get_cell(x - 1, y) {
+        if x > 0 && !retval[y][x - 1] && target_tile == *canvas.get_cell(layer, x - 1, y) {
             unchecked.push((x - 1, y));
         }
-        if x < end_x && !retval[y][x + 1] && target_tile == *canvas.get_cell(x + 1, y) {
+        if x < end_x && !retval[y][x + 1] && target_tile == *canvas.get_cell(layer, x + 1, y) {
             unchecked.push((x + 1, y));
         }
-        if y > 0 && !retval[y - 1][x] && target_tile == *canvas.get_cell(x, y - 1) {
+        if y > 0 && !retval[y - 1][x] && target_tile == *canvas.get_cell(layer, x, y - 1) {
             unchecked.push((x, y - 1));
         }
-        if y < end_y && !retval[y + 1][x] && target_tile == *canvas.get_cell(x, y + 1) {
+        if y < end_y && !retval[y + 1][x] && target_tile == *canvas.get_cell(layer, x, y + 1) {
             unchecked.push((x, y + 1));
         }
     }
@@ -104,200 +308,1339 @@ impl Command {
     pub fn new(
         x: usize,
         y: usize,
-        pen: &PencilState,
+        pen: &Pen,
         cur_tile: &Option<TileState>,
         need_swap: bool,
         canvas: &Canvas,
     ) -> Self {
+        let layer = pen.layer;
+        // the layer may be gone after an undo or a layer removal
+        match canvas.layers.get(layer) {
+            Some(l) if !l.locked => {}
+            _ => return Self::None,
+        }
         let tile = if need_swap {
-            pen.swap_color_and_into()
+            pen.swapped_tile_state()
         } else {
-            pen.into_tile_state(cur_tile)
+            pen.to_tile_state(cur_tile)
         };
         match pen.tool {
-            ToolEnum::Pencil => Self::Point { c: tile, x, y },
-            ToolEnum::Fill => Self::Fill(FillPos {
+            ToolEnum::Pencil => Self::Point {
+                layer,
+                c: tile,
+                x,
+                y,
+            },
+            ToolEnum::Fill => Self::Fill {
+                layer,
                 c: tile,
-                cells: compute_contigeous_cell(canvas, x, y),
                 x,
                 y,
-            }),
-            ToolEnum::Eraser => Self::Point { c: None, x, y },
+            },
+            ToolEnum::Select | ToolEnum::Text => Self::None,
+            ToolEnum::Eraser => Self::Point {
+                layer,
+                c: None,
+                x,
+                y,
+            },
             ToolEnum::Replace => Self::Replace {
+                layer,
                 x,
                 y,
                 replace_with: tile,
             },
-            ToolEnum::RectFilled => {
-                if pen.start_xy == None || pen.to_xy == None {
-                    Self::None
+            ToolEnum::RectFilled
+            | ToolEnum::RectOutline
+            | ToolEnum::EllipseFilled
+            | ToolEnum::EllipseOutline
+            | ToolEnum::Line => {
+                if let (Some(start_xy), Some(to_xy)) = (pen.start_xy, pen.to_xy) {
+                    Self::new_shape(pen, tile, start_xy, to_xy)
                 } else {
-                    let (mut start_x, mut start_y) = pen.start_xy.unwrap();
-                    let (mut to_x, mut to_y) = pen.to_xy.unwrap();
-                    if to_x < start_x {
-                        std::mem::swap(&mut start_x, &mut to_x)
-                    }
-                    if to_y < start_y {
-                        std::mem::swap(&mut start_y, &mut to_y)
-                    }
-                    Self::Rect {
-                        c: tile,
-                        filled: true,
-                        start_x,
-                        start_y,
-                        to_x,
-                        to_y,
-                    }
+                    Self::None
                 }
             }
         }
     }
-}
 
-fn excute_painting_command_to_canvas_mut(canvas: &mut Canvas, commands: &[Command]) {
-    for command in commands {
-        match command.clone() {
-            Command::Point { c, x, y } => {
-                *canvas.get_cell_mut(x, y) = c;
-            }
-            Command::ChangeCanvasSize {
-                width,
-                height,
+    pub fn new_shape(
+        pen: &Pen,
+        c: Option<TileState>,
+        (mut start_x, mut start_y): (usize, usize),
+        (mut to_x, mut to_y): (usize, usize),
+    ) -> Self {
+        let layer = pen.layer;
+        if pen.tool == ToolEnum::Line {
+            return Self::Line {
+                layer,
+                c,
+                box_drawing: pen.box_drawing,
                 start_x,
                 start_y,
                 to_x,
                 to_y,
-            } => {
-                canvas.change_canvas_size(width, height, start_x, start_y, to_x, to_y);
-            }
-            Command::Fill(FillPos {
+            };
+        }
+        if to_x < start_x {
+            std::mem::swap(&mut start_x, &mut to_x)
+        }
+        if to_y < start_y {
+            std::mem::swap(&mut start_y, &mut to_y)
+        }
+        if matches!(pen.tool, ToolEnum::EllipseFilled | ToolEnum::EllipseOutline) {
+            return Self::Ellipse {
+                layer,
                 c,
-                cells,
-                x: _,
-                y: _,
-            }) => {
-                for (y, row) in cells.iter().enumerate() {
-                    for (x, &need_filled) in row.iter().enumerate() {
-                        if need_filled {
-                            *canvas.get_cell_mut(x, y) = c;
-                        }
-                    }
-                }
-            }
-            Command::Replace { x, y, replace_with } => {
-                let &target_tile = canvas.get_cell(x, y);
-                canvas.cells.iter_mut().for_each(|cur| {
-                    if *cur == target_tile {
-                        *cur = replace_with;
-                    }
-                });
-            }
+                filled: pen.tool == ToolEnum::EllipseFilled,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+            };
+        }
+        let filled = pen.tool == ToolEnum::RectFilled;
+        Self::Rect {
+            layer,
+            c,
+            filled,
+            box_drawing: if filled {
+                BoxDrawing::Off
+            } else {
+                pen.box_drawing
+            },
+            start_x,
+            start_y,
+            to_x,
+            to_y,
+        }
+    }
+
+    /// the cells painted by a shape command, shared by drawing and previewing
+    pub fn shape_cells(&self) -> Vec<(usize, usize, Option<TileState>)> {
+        let mut retval = Vec::new();
+        match *self {
             Command::Rect {
                 c,
                 filled,
+                box_drawing,
                 start_x,
                 start_y,
                 to_x,
                 to_y,
+                ..
             } => {
                 if filled {
                     for y in start_y..=to_y {
                         for x in start_x..=to_x {
-                            *canvas.get_cell_mut(x, y) = c;
+                            retval.push((x, y, c));
                         }
                     }
                 } else {
+                    let border = |x, y| {
+                        let glyph =
+                            box_drawing.rect_glyph((x, y), (start_x, start_y), (to_x, to_y));
+                        match (c, glyph) {
+                            (Some(tile), Some(idx)) => (x, y, Some(TileState { idx, ..tile })),
+                            _ => (x, y, c),
+                        }
+                    };
                     for y in [start_y, to_y] {
                         for x in start_x..=to_x {
-                            *canvas.get_cell_mut(x, y) = c;
+                            retval.push(border(x, y));
                         }
                     }
                     for x in [start_x, to_x] {
                         for y in start_y + 1..to_y {
-                            *canvas.get_cell_mut(x, y) = c;
+                            retval.push(border(x, y));
                         }
                     }
                 }
             }
-            Command::None => {}
+            Command::Ellipse {
+                c,
+                filled,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => {
+                for (x, y) in shape::ellipse((start_x, start_y), (to_x, to_y), filled) {
+                    retval.push((x, y, c));
+                }
+            }
+            Command::Line {
+                c,
+                box_drawing,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => {
+                let c = match (c, box_drawing.line_glyph((start_x, start_y), (to_x, to_y))) {
+                    (Some(tile), Some(idx)) => Some(TileState { idx, ..tile }),
+                    _ => c,
+                };
+                for (x, y) in shape::line((start_x, start_y), (to_x, to_y)) {
+                    retval.push((x, y, c));
+                }
+            }
+            _ => {}
+        }
+        retval
+    }
+}
+
+impl Edit {
+    /// applies the edit and returns the one undoing it
+    fn apply(self, canvas: &mut Canvas) -> Edit {
+        match self {
+            Edit::Cells { layer, mut cells } => {
+                let layer_cells = &mut canvas.layers[layer].cells;
+                for (i, tile) in cells.iter_mut() {
+                    std::mem::swap(&mut layer_cells[*i], tile);
+                }
+                // a cell set twice gets its first value back last
+                cells.reverse();
+                Edit::Cells { layer, cells }
+            }
+            Edit::InsertLayer { idx, layer } => {
+                canvas.layers.insert(idx, layer);
+                Edit::RemoveLayer { idx }
+            }
+            Edit::RemoveLayer { idx } => Edit::InsertLayer {
+                idx,
+                layer: canvas.layers.remove(idx),
+            },
+            Edit::MoveLayer { from, to } => {
+                canvas.move_layer(from, to);
+                Edit::MoveLayer { from: to, to: from }
+            }
+            Edit::LayerProps {
+                layer,
+                mut name,
+                mut visible,
+                mut locked,
+            } => {
+                let cur = &mut canvas.layers[layer];
+                std::mem::swap(&mut cur.name, &mut name);
+                std::mem::swap(&mut cur.visible, &mut visible);
+                std::mem::swap(&mut cur.locked, &mut locked);
+                Edit::LayerProps {
+                    layer,
+                    name,
+                    visible,
+                    locked,
+                }
+            }
+            Edit::Canvas(mut other) => {
+                std::mem::swap(canvas, &mut other);
+                Edit::Canvas(other)
+            }
+            Edit::Group(edits) => {
+                let mut undo: Vec<Edit> = edits.into_iter().map(|e| e.apply(canvas)).collect();
+                undo.reverse();
+                Edit::Group(undo)
+            }
         }
     }
 }
 
-fn excute_painting_command_to_canvas(canvas: &Canvas, commands: &[Command]) -> Canvas {
-    let mut canvas = canvas.clone();
-    excute_painting_command_to_canvas_mut(&mut canvas, commands);
-    canvas
+impl Edit {
+    /// [`Edit::apply`] without giving back the inverse, leaving the edit as it is
+    fn apply_copy(&self, canvas: &mut Canvas) {
+        match self {
+            Edit::Cells { layer, cells } => {
+                let layer_cells = &mut canvas.layers[*layer].cells;
+                for &(i, tile) in cells {
+                    layer_cells[i] = tile;
+                }
+            }
+            Edit::InsertLayer { idx, layer } => canvas.layers.insert(*idx, layer.clone()),
+            Edit::RemoveLayer { idx } => {
+                canvas.layers.remove(*idx);
+            }
+            Edit::MoveLayer { from, to } => canvas.move_layer(*from, *to),
+            Edit::LayerProps {
+                layer,
+                name,
+                visible,
+                locked,
+            } => {
+                let cur = &mut canvas.layers[*layer];
+                cur.name = name.clone();
+                cur.visible = *visible;
+                cur.locked = *locked;
+            }
+            Edit::Canvas(other) => *canvas = (**other).clone(),
+            Edit::Group(edits) => edits.iter().for_each(|edit| edit.apply_copy(canvas)),
+        }
+    }
+
+    /// applies the edit like [`Edit::apply`], None when it does not fit the canvas
+    ///
+    /// the canvas and the edit are left half done then, for edits read from a file
+    fn checked_apply(self, canvas: &mut Canvas) -> Option<Edit> {
+        let len = canvas.layers.len();
+        let size = canvas.width * canvas.height;
+        let fits = match &self {
+            Edit::Cells { layer, cells } => *layer < len && cells.iter().all(|&(i, _)| i < size),
+            Edit::InsertLayer { idx, layer } => {
+                *idx <= len && len < MAX_LAYERS && layer.cells.len() == size
+            }
+            Edit::RemoveLayer { idx } => *idx < len && len > 1,
+            Edit::MoveLayer { from, to } => *from < len && *to < len,
+            Edit::LayerProps { layer, .. } => *layer < len,
+            Edit::Canvas(other) => {
+                (1..=MAX_LAYERS).contains(&other.layers.len())
+                    && validate::canvas_size(other.width, other.height).is_some_and(|size| {
+                        other.layers.iter().all(|layer| layer.cells.len() == size)
+                    })
+            }
+            Edit::Group(_) => true,
+        };
+        if !fits {
+            return None;
+        }
+        let Edit::Group(edits) = self else {
+            return Some(self.apply(canvas));
+        };
+        let mut undo = Vec::with_capacity(edits.len());
+        for edit in edits {
+            undo.push(edit.checked_apply(canvas)?);
+        }
+        undo.reverse();
+        Some(Edit::Group(undo))
+    }
+}
+
+/// sets cells of a layer by index, None when none of them changed
+fn set_cells(
+    canvas: &mut Canvas,
+    layer: usize,
+    cells: impl IntoIterator<Item = (usize, Option<TileState>)>,
+) -> Option<Edit> {
+    let layer_cells = &mut canvas.layers[layer].cells;
+    let mut undo: Vec<_> = cells
+        .into_iter()
+        .filter_map(|(i, tile)| {
+            let old = std::mem::replace(&mut layer_cells[i], tile);
+            (old != tile).then_some((i, old))
+        })
+        .collect();
+    if undo.is_empty() {
+        return None;
+    }
+    undo.reverse();
+    Some(Edit::Cells { layer, cells: undo })
+}
+
+fn set_layer_props(
+    canvas: &mut Canvas,
+    layer: usize,
+    name: String,
+    visible: bool,
+    locked: bool,
+) -> Option<Edit> {
+    let cur = &canvas.layers[layer];
+    if cur.name == name && cur.visible == visible && cur.locked == locked {
+        return None;
+    }
+    Some(
+        Edit::LayerProps {
+            layer,
+            name,
+            visible,
+            locked,
+        }
+        .apply(canvas),
+    )
+}
+
+/// the indices of the cells in a region, both corners included
+fn region(
+    canvas: &Canvas,
+    (start_x, start_y): (usize, usize),
+    (to_x, to_y): (usize, usize),
+) -> Vec<usize> {
+    (start_y..=to_y)
+        .flat_map(|y| (start_x..=to_x).map(move |x| y * canvas.width + x))
+        .collect()
+}
+
+/// the cells a clip pastes at `x, y`, `None` cells of the clip are transparent
+fn clip_cells(canvas: &Canvas, x: usize, y: usize, clip: &Clip) -> Vec<(usize, Option<TileState>)> {
+    let mut retval = Vec::new();
+    for cy in 0..clip.height {
+        for cx in 0..clip.width {
+            if x + cx >= canvas.width || y + cy >= canvas.height {
+                continue;
+            }
+            if let Some(tile) = clip.cells[cy * clip.width + cx] {
+                retval.push(((y + cy) * canvas.width + x + cx, Some(tile)));
+            }
+        }
+    }
+    retval
+}
+
+impl Command {
+    /// describes the command before it is applied to `canvas`, None for [`Command::None`]
+    fn label(&self, canvas: &Canvas) -> Option<Label> {
+        let layer_name = |layer: usize| {
+            canvas
+                .layers
+                .get(layer)
+                .map(|layer| layer.name.clone())
+                .unwrap_or_default()
+        };
+        let label = match *self {
+            // never kept, it changes nothing
+            Command::None => return None,
+            Command::Point { c, x, y, .. } => Label::Point {
+                x,
+                y,
+                erase: c.is_none(),
+            },
+            Command::ChangeCanvasSize { width, height, .. } => Label::Resize { width, height },
+            Command::Fill { x, y, .. } => Label::Fill { x, y },
+            Command::Replace { x, y, .. } => Label::Replace { x, y },
+            Command::Rect {
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => Label::Rect {
+                from: (start_x, start_y),
+                to: (to_x, to_y),
+            },
+            Command::Ellipse {
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => Label::Ellipse {
+                from: (start_x, start_y),
+                to: (to_x, to_y),
+            },
+            Command::Line {
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => Label::Line {
+                from: (start_x, start_y),
+                to: (to_x, to_y),
+            },
+            Command::Text { ref cells, .. } => Label::Text { chars: cells.len() },
+            Command::Paste { x, y, .. } => Label::Paste { x, y },
+            Command::Clear {
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                ..
+            } => Label::Clear {
+                from: (start_x, start_y),
+                to: (to_x, to_y),
+            },
+            Command::MoveRegion {
+                start_x,
+                start_y,
+                x,
+                y,
+                ..
+            } => Label::Move {
+                from: (start_x, start_y),
+                to: (x, y),
+            },
+            Command::AddLayer { ref name, .. } => Label::AddLayer { name: name.clone() },
+            Command::RemoveLayer { idx } => Label::RemoveLayer {
+                name: layer_name(idx),
+            },
+            Command::MoveLayer { from, .. } => Label::MoveLayer {
+                name: layer_name(from),
+            },
+            Command::RenameLayer { ref name, .. } => Label::RenameLayer { name: name.clone() },
+            Command::SetLayerVisible { layer, visible } => Label::ShowLayer {
+                name: layer_name(layer),
+                visible,
+            },
+            Command::SetLayerLocked { layer, locked } => Label::LockLayer {
+                name: layer_name(layer),
+                locked,
+            },
+        };
+        Some(label)
+    }
+
+    /// applies the command to the canvas and returns the edit undoing it, None when nothing changed
+    fn apply(self, canvas: &mut Canvas) -> Option<Edit> {
+        let width = canvas.width;
+        match self {
+            Command::None => None,
+            Command::Point { layer, c, x, y } => set_cells(canvas, layer, [(y * width + x, c)]),
+            Command::ChangeCanvasSize {
+                width,
+                height,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+            } => {
+                let old = canvas.clone();
+                canvas.change_canvas_size(width, height, start_x, start_y, to_x, to_y);
+                Some(Edit::Canvas(Box::new(old)))
+            }
+            Command::Fill { layer, c, x, y } => {
+                let mask = compute_contigeous_cell(canvas, layer, x, y);
+                let cells: Vec<_> = mask
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .filter(|(_, &need_filled)| need_filled)
+                    .map(|(i, _)| (i, c))
+                    .collect();
+                set_cells(canvas, layer, cells)
+            }
+            Command::Replace {
+                layer,
+                x,
+                y,
+                replace_with,
+            } => {
+                let &target_tile = canvas.get_cell(layer, x, y);
+                let cells: Vec<_> = canvas.layers[layer]
+                    .cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &cur)| cur == target_tile)
+                    .map(|(i, _)| (i, replace_with))
+                    .collect();
+                set_cells(canvas, layer, cells)
+            }
+            Command::Rect { layer, .. }
+            | Command::Ellipse { layer, .. }
+            | Command::Line { layer, .. } => {
+                let cells = self.shape_cells();
+                set_cells(
+                    canvas,
+                    layer,
+                    cells.into_iter().map(|(x, y, c)| (y * width + x, c)),
+                )
+            }
+            Command::Text { layer, cells } => set_cells(
+                canvas,
+                layer,
+                cells
+                    .into_iter()
+                    .map(|(x, y, tile)| (y * width + x, Some(tile))),
+            ),
+            Command::Paste { layer, x, y, clip } => {
+                let cells = clip_cells(canvas, x, y, &clip);
+                set_cells(canvas, layer, cells)
+            }
+            Command::Clear {
+                layer,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+            } => {
+                let cells = region(canvas, (start_x, start_y), (to_x, to_y));
+                set_cells(canvas, layer, cells.into_iter().map(|i| (i, None)))
+            }
+            Command::MoveRegion {
+                layer,
+                start_x,
+                start_y,
+                to_x,
+                to_y,
+                x,
+                y,
+            } => {
+                let clip = canvas.copy_region(layer, start_x, start_y, to_x, to_y);
+                let mut cells: Vec<_> = region(canvas, (start_x, start_y), (to_x, to_y))
+                    .into_iter()
+                    .map(|i| (i, None))
+                    .collect();
+                cells.extend(clip_cells(canvas, x, y, &clip));
+                set_cells(canvas, layer, cells)
+            }
+            Command::AddLayer { idx, name } => {
+                if canvas.layers.len() >= MAX_LAYERS {
+                    return None;
+                }
+                let edit = Edit::InsertLayer {
+                    idx: idx.min(canvas.layers.len()),
+                    layer: Layer::with_size(name, canvas.width * canvas.height),
+                };
+                Some(edit.apply(canvas))
+            }
+            Command::RemoveLayer { idx } => {
+                if canvas.layers.len() <= 1 || idx >= canvas.layers.len() {
+                    return None;
+                }
+                Some(Edit::RemoveLayer { idx }.apply(canvas))
+            }
+            Command::MoveLayer { from, to } => {
+                let len = canvas.layers.len();
+                if from == to || from >= len || to >= len {
+                    return None;
+                }
+                Some(Edit::MoveLayer { from, to }.apply(canvas))
+            }
+            Command::RenameLayer { layer, name } => {
+                let cur = &canvas.layers[layer];
+                let (visible, locked) = (cur.visible, cur.locked);
+                set_layer_props(canvas, layer, name, visible, locked)
+            }
+            Command::SetLayerVisible { layer, visible } => {
+                let cur = &canvas.layers[layer];
+                let (name, locked) = (cur.name.clone(), cur.locked);
+                set_layer_props(canvas, layer, name, visible, locked)
+            }
+            Command::SetLayerLocked { layer, locked } => {
+                let cur = &canvas.layers[layer];
+                let (name, visible) = (cur.name.clone(), cur.visible);
+                set_layer_props(canvas, layer, name, visible, locked)
+            }
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl History {
     pub fn new() -> Self {
         Self {
-            edit_history: Vec::new(),
-            undo_history: Vec::new(),
-            last_command: 0,
-            rendering_canvas: None,
+            nodes: vec![Node {
+                label: Label::Start,
+                edit: Edit::Group(Vec::new()),
+                parent: 0,
+                children: Vec::new(),
+                redo_child: None,
+                time: now(),
+            }],
+            current: 0,
+            saved_at: Some(0),
+            group: None,
+        }
+    }
+
+    /// whether the document differs from the last save
+    pub fn is_dirty(&self) -> bool {
+        self.saved_at != Some(self.current)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_at = Some(self.current);
+    }
+
+    /// the step the canvas is at
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn label(&self, step: usize) -> &Label {
+        &self.nodes[step].label
+    }
+
+    /// when the step was made, in seconds since the unix epoch
+    pub fn time(&self, step: usize) -> u64 {
+        self.nodes[step].time
+    }
+
+    /// how many branches start after the step
+    pub fn branch_count(&self, step: usize) -> usize {
+        self.nodes[step].children.len()
+    }
+
+    /// the steps leading to `step`, from the start up to and including it
+    fn path_to(&self, mut step: usize) -> Vec<usize> {
+        let mut path = vec![step];
+        while step != 0 {
+            step = self.nodes[step].parent;
+            path.push(step);
+        }
+        path.reverse();
+        path
+    }
+
+    /// the last step redo reaches from `step`
+    fn tip(&self, mut step: usize) -> usize {
+        while let Some(child) = self.nodes[step].redo_child {
+            step = child;
+        }
+        step
+    }
+
+    /// the branch the canvas is on, the steps up to the current one then those redo goes through
+    pub fn branch(&self) -> Vec<usize> {
+        self.path_to(self.tip(self.current))
+    }
+
+    /// collects the commands pushed from now on into a single undo step
+    pub fn begin_group(&mut self) {
+        if self.group.is_none() {
+            self.group = Some(None);
+        }
+    }
+
+    /// closes the group opened by [`History::begin_group`]
+    pub fn end_group(&mut self) {
+        let Some(Some(step)) = self.group.take() else {
+            return;
+        };
+        let node = &mut self.nodes[step];
+        if let Edit::Group(edits) = &mut node.edit {
+            // undoing goes through the edits of the group backwards
+            edits.reverse();
+            if edits.len() == 1 {
+                node.edit = edits.pop().unwrap();
+            } else if let Label::Point { .. } = node.label {
+                node.label = Label::Stroke { cells: edits.len() };
+            }
+        }
+    }
+
+    /// applies or reverts the edit of `step`, which flips it the other way
+    fn flip(&mut self, canvas: &mut Canvas, step: usize) {
+        let node = &mut self.nodes[step];
+        let edit = std::mem::replace(&mut node.edit, Edit::Group(Vec::new()));
+        node.edit = edit.apply(canvas);
+    }
+
+    fn step_back(&mut self, canvas: &mut Canvas) {
+        let step = self.current;
+        self.flip(canvas, step);
+        self.current = self.nodes[step].parent;
+        self.nodes[self.current].redo_child = Some(step);
+    }
+
+    fn step_into(&mut self, canvas: &mut Canvas, child: usize) {
+        self.flip(canvas, child);
+        self.nodes[self.current].redo_child = Some(child);
+        self.current = child;
+    }
+
+    pub fn undo(&mut self, canvas: &mut Canvas) {
+        self.end_group();
+        if self.current != 0 {
+            self.step_back(canvas);
+        }
+    }
+
+    /// redoes into the branch visited last
+    pub fn redo(&mut self, canvas: &mut Canvas) {
+        self.end_group();
+        if let Some(child) = self.nodes[self.current].redo_child {
+            self.step_into(canvas, child);
+        }
+    }
+
+    /// undoes and redoes through the tree until the canvas is at `step`
+    pub fn jump_to(&mut self, canvas: &mut Canvas, step: usize) {
+        self.end_group();
+        if step >= self.nodes.len() {
+            return;
+        }
+        let path = self.path_to(step);
+        let mut on_path = vec![false; self.nodes.len()];
+        path.iter().for_each(|&step| on_path[step] = true);
+        while !on_path[self.current] {
+            self.step_back(canvas);
+        }
+        let fork = path.iter().position(|&step| step == self.current).unwrap();
+        for &child in &path[fork + 1..] {
+            self.step_into(canvas, child);
         }
     }
 
-    pub fn undo(&mut self) {
-        if let Some(command) = self.edit_history.pop() {
-            self.undo_history.push(command);
-            let last_command = self.edit_history.len() / COMMAND_EXCUTE_GAP;
-            if last_command < self.last_command {
-                self.last_command = 0;
-                self.rendering_canvas = None;
+    /// the canvas as it is at `step`, `canvas` being the current one
+    ///
+    /// works on a copy and leaves the history as it is, an open group stays open
+    pub fn preview(&self, canvas: &Canvas, step: usize) -> Canvas {
+        let mut canvas = canvas.clone();
+        if step >= self.nodes.len() {
+            return canvas;
+        }
+        let (from, to) = (self.path_to(self.current), self.path_to(step));
+        let fork = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+        // steps up to the current one hold the edits undoing them, the others those redoing them
+        for &step in from[fork..].iter().rev() {
+            match &self.nodes[step].edit {
+                // the edits of a group are only put in undo order once it is closed
+                Edit::Group(edits) if self.group == Some(Some(step)) => edits
+                    .iter()
+                    .rev()
+                    .for_each(|edit| edit.apply_copy(&mut canvas)),
+                edit => edit.apply_copy(&mut canvas),
             }
         }
+        for &step in &to[fork..] {
+            self.nodes[step].edit.apply_copy(&mut canvas);
+        }
+        canvas
+    }
+
+    /// whether a fork up to the current step leads to another branch
+    pub fn can_switch_branch(&self) -> bool {
+        self.path_to(self.current)
+            .iter()
+            .any(|&step| self.nodes[step].children.len() > 1)
     }
 
-    pub fn redo(&mut self) {
-        if let Some(command) = self.undo_history.pop() {
-            self.edit_history.push(command);
+    /// goes to the end of the previous or next branch at the fork closest to the current step
+    ///
+    /// returns false when the canvas is on the only branch there is
+    pub fn switch_branch(&mut self, canvas: &mut Canvas, forward: bool) -> bool {
+        let path = self.path_to(self.current);
+        let Some(fork) = path
+            .iter()
+            .rposition(|&step| self.nodes[step].children.len() > 1)
+        else {
+            return false;
+        };
+        let children = &self.nodes[path[fork]].children;
+        // at the fork itself the branch is the one redo would take
+        let on = match path.get(fork + 1) {
+            Some(step) => *step,
+            None => self.nodes[path[fork]].redo_child.unwrap_or(children[0]),
+        };
+        let idx = children.iter().position(|&step| step == on).unwrap();
+        let len = children.len();
+        let other = children[if forward {
+            (idx + 1) % len
+        } else {
+            (idx + len - 1) % len
+        }];
+        let tip = self.tip(other);
+        self.jump_to(canvas, tip);
+        true
+    }
+
+    /// the state of the canvas at `time`, the last step made up to then
+    pub fn step_at(&self, time: u64) -> usize {
+        // steps are numbered in the order they were made
+        self.nodes
+            .iter()
+            .rposition(|node| node.time <= time)
+            .unwrap_or(0)
+    }
+
+    /// when the first and the last step were made
+    pub fn time_range(&self) -> (u64, u64) {
+        (self.nodes[0].time, self.nodes[self.nodes.len() - 1].time)
+    }
+
+    /// brings the canvas back to how it was at `time`, whatever branch it was on
+    pub fn travel_to(&mut self, canvas: &mut Canvas, time: u64) {
+        self.jump_to(canvas, self.step_at(time));
+    }
+
+    /// gives the history back when its steps link up and every edit fits `canvas`, the canvas it is at
+    ///
+    /// for histories read from a file, undoing through a broken one would panic
+    pub fn validated(mut self, canvas: &Canvas) -> Option<Self> {
+        let len = self.nodes.len();
+        if len == 0 || self.nodes[0].parent != 0 || self.current >= len {
+            return None;
+        }
+        let mut child_count = 0;
+        for (step, node) in self.nodes.iter().enumerate() {
+            // steps are numbered in the order they were made, so a parent comes first
+            if step != 0 && node.parent >= step {
+                return None;
+            }
+            for (i, &child) in node.children.iter().enumerate() {
+                if child >= len || self.nodes[child].parent != step || child == 0 {
+                    return None;
+                }
+                if node.children[..i].contains(&child) {
+                    return None;
+                }
+            }
+            if node
+                .redo_child
+                .is_some_and(|child| !node.children.contains(&child))
+            {
+                return None;
+            }
+            child_count += node.children.len();
+        }
+        // every step but the start is the child of its parent
+        if child_count != len - 1 {
+            return None;
+        }
+
+        // goes through every edit on a copy, back to the start then into each branch and out again
+        let mut canvas = canvas.clone();
+        let path = self.path_to(self.current);
+        for &step in path[1..].iter().rev() {
+            if !self.checked_flip(&mut canvas, step) {
+                return None;
+            }
+        }
+        enum Visit {
+            Into(usize),
+            Out(usize),
+        }
+        let mut visits: Vec<_> = self.nodes[0]
+            .children
+            .iter()
+            .map(|&c| Visit::Into(c))
+            .collect();
+        while let Some(visit) = visits.pop() {
+            match visit {
+                Visit::Into(step) => {
+                    if !self.checked_flip(&mut canvas, step) {
+                        return None;
+                    }
+                    visits.push(Visit::Out(step));
+                    visits.extend(self.nodes[step].children.iter().map(|&c| Visit::Into(c)));
+                }
+                // undoing what was just done always fits
+                Visit::Out(step) => self.flip(&mut canvas, step),
+            }
+        }
+        for &step in &path[1..] {
+            self.flip(&mut canvas, step);
+        }
+        Some(self)
+    }
+
+    /// [`History::flip`] for edits that may not fit the canvas, false when the step does not
+    fn checked_flip(&mut self, canvas: &mut Canvas, step: usize) -> bool {
+        let node = &mut self.nodes[step];
+        let edit = std::mem::replace(&mut node.edit, Edit::Group(Vec::new()));
+        match edit.checked_apply(canvas) {
+            Some(edit) => {
+                node.edit = edit;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// applies the command to the canvas, commands that change nothing are not kept
+    pub fn push(&mut self, canvas: &mut Canvas, command: Command) {
+        let Some(label) = command.label(canvas) else {
+            return;
+        };
+        let Some(undo) = command.apply(canvas) else {
+            return;
+        };
+        match self.group {
+            Some(Some(step)) if step == self.current => {
+                if let Edit::Group(edits) = &mut self.nodes[step].edit {
+                    edits.push(undo);
+                }
+                // the group keeps growing, so it is not the state that was saved any more
+                if self.saved_at == Some(step) {
+                    self.saved_at = None;
+                }
+            }
+            group => {
+                let edit = if group.is_some() {
+                    Edit::Group(vec![undo])
+                } else {
+                    undo
+                };
+                let step = self.nodes.len();
+                self.nodes.push(Node {
+                    label,
+                    edit,
+                    parent: self.current,
+                    children: Vec::new(),
+                    redo_child: None,
+                    time: now(),
+                });
+                let parent = &mut self.nodes[self.current];
+                parent.children.push(step);
+                parent.redo_child = Some(step);
+                self.current = step;
+                if group.is_some() {
+                    self.group = Some(Some(step));
+                }
+            }
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::Color;
+
+    fn draw(history: &mut History, canvas: &mut Canvas, pen: &Pen, x: usize, y: usize) {
+        let command = Command::new(x, y, pen, canvas.get_cell(pen.layer, x, y), false, canvas);
+        history.push(canvas, command);
+    }
+
+    fn snapshot(canvas: &Canvas) -> String {
+        serde_json::to_string(canvas).unwrap()
+    }
 
-    pub fn clear_undo(&mut self) {
-        self.undo_history.clear();
+    #[test]
+    fn test_command_on_missing_layer() {
+        let mut canvas = Canvas::with_size(4, 4);
+        let pen = Pen {
+            layer: 3,
+            ..Default::default()
+        };
+        let command = Command::new(0, 0, &pen, &None, false, &canvas);
+        assert_eq!(command, Command::None);
+        // no step is listed for it
+        let mut history = History::new();
+        history.push(&mut canvas, command);
+        assert_eq!(history.nodes.len(), 1);
     }
 
-    pub fn excute_on_canvas(&mut self, canvas: &Canvas) -> Canvas {
-        if let None = self.rendering_canvas {
-            self.rendering_canvas = Some(canvas.clone());
+    #[test]
+    fn test_history_dirty() {
+        let mut canvas = Canvas::with_size(4, 4);
+        let pen = Pen::default();
+        let mut history = History::new();
+        assert!(!history.is_dirty());
+        draw(&mut history, &mut canvas, &pen, 0, 0);
+        assert!(history.is_dirty());
+        history.mark_saved();
+        draw(&mut history, &mut canvas, &pen, 1, 0);
+        history.undo(&mut canvas);
+        assert!(!history.is_dirty());
+        history.undo(&mut canvas);
+        assert!(history.is_dirty());
+        history.redo(&mut canvas);
+        assert!(!history.is_dirty());
+        history.undo(&mut canvas);
+        // drawing after undoing starts a branch next to the saved state
+        draw(&mut history, &mut canvas, &pen, 2, 0);
+        history.undo(&mut canvas);
+        history.redo(&mut canvas);
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn test_history_undo_redo() {
+        let mut canvas = Canvas::with_size(8, 4);
+        let blank = canvas.clone();
+        let pen = Pen {
+            idx: 7,
+            ..Default::default()
+        };
+        let mut history = History::new();
+        for x in 0..18 {
+            draw(&mut history, &mut canvas, &pen, x % 8, x / 8);
         }
+        assert_eq!(
+            canvas.get_cell(0, 1, 2),
+            &Some(TileState {
+                idx: 7,
+                fc: Color::WHITE,
+                bc: Color::BLACK,
+            })
+        );
+        // painting a cell with what it already holds is not an undo step
+        draw(&mut history, &mut canvas, &pen, 0, 0);
+        for _ in 0..18 {
+            history.undo(&mut canvas);
+        }
+        assert_eq!(snapshot(&canvas), snapshot(&blank));
+        history.redo(&mut canvas);
+        assert_eq!(canvas.get_cell(0, 0, 0).unwrap().idx, 7);
+    }
 
-        let last_command = self.edit_history.len() / COMMAND_EXCUTE_GAP;
+    #[test]
+    fn test_history_group() {
+        let mut canvas = Canvas::with_size(8, 8);
+        let pen = Pen::default();
+        let mut history = History::new();
+        draw(&mut history, &mut canvas, &pen, 6, 1);
+        history.begin_group();
+        for x in 0..4 {
+            draw(&mut history, &mut canvas, &pen, x, 7);
+        }
+        // crossing the stroke paints a cell twice
+        let eraser = Pen {
+            tool: ToolEnum::Eraser,
+            ..Default::default()
+        };
+        draw(&mut history, &mut canvas, &eraser, 1, 7);
+        // looking at the history mid-stroke leaves the stroke a single step
+        let before = history.preview(&canvas, 1);
+        assert!((0..4).all(|x| before.get_cell(0, x, 7).is_none()));
+        draw(&mut history, &mut canvas, &pen, 1, 7);
+        history.end_group();
+        assert_eq!(history.nodes.len(), 3);
+        let painted = snapshot(&canvas);
+        assert!((0..4).all(|x| canvas.get_cell(0, x, 7).is_some()));
+
+        history.undo(&mut canvas);
+        assert!((0..4).all(|x| canvas.get_cell(0, x, 7).is_none()));
+        assert!(canvas.get_cell(0, 6, 1).is_some());
+        history.redo(&mut canvas);
+        assert_eq!(snapshot(&canvas), painted);
+    }
 
-        if last_command != self.last_command {
-            excute_painting_command_to_canvas_mut(
-                self.rendering_canvas.as_mut().unwrap(),
-                &self.edit_history[self.last_command * COMMAND_EXCUTE_GAP
-                    ..(self.last_command * COMMAND_EXCUTE_GAP)
-                        + (last_command - self.last_command) * COMMAND_EXCUTE_GAP],
-            );
-            self.last_command = last_command;
+    #[test]
+    fn test_history_validated() {
+        let mut canvas = Canvas::with_size(6, 4);
+        let pen = Pen::default();
+        let mut history = History::new();
+        draw(&mut history, &mut canvas, &pen, 5, 3);
+        history.push(
+            &mut canvas,
+            Command::AddLayer {
+                idx: 1,
+                name: "top".to_string(),
+            },
+        );
+        history.undo(&mut canvas);
+        draw(&mut history, &mut canvas, &pen, 0, 0);
+        let json = serde_json::to_value(&history).unwrap();
+        let load =
+            |json: &serde_json::Value| -> History { serde_json::from_value(json.clone()).unwrap() };
+        let history = load(&json).validated(&canvas).unwrap();
+        // checking went through every edit and put each back
+        let mut copy = canvas.clone();
+        let mut checked = history;
+        checked.switch_branch(&mut copy, true);
+        assert_eq!(copy.layers.len(), 2);
+
+        // the cells of the steps are past the end of a smaller canvas
+        assert!(load(&json).validated(&Canvas::with_size(2, 2)).is_none());
+        let mut broken = json.clone();
+        broken["current"] = 9.into();
+        assert!(load(&broken).validated(&canvas).is_none());
+        let mut broken = json.clone();
+        broken["nodes"][1]["parent"] = 3.into();
+        assert!(load(&broken).validated(&canvas).is_none());
+        let mut broken = json.clone();
+        broken["nodes"][0]["redo_child"] = 1.into();
+        broken["nodes"][0]["children"] = serde_json::json!([2]);
+        assert!(load(&broken).validated(&canvas).is_none());
+        let mut broken = json;
+        broken["nodes"] = serde_json::json!([]);
+        assert!(load(&broken).validated(&canvas).is_none());
+    }
+
+    #[test]
+    fn test_history_undo_past_save() {
+        let mut canvas = Canvas::with_size(8, 8);
+        let pen = Pen::default();
+        let mut history = History::new();
+        for x in 0..32 {
+            draw(&mut history, &mut canvas, &pen, x % 8, x / 8);
+        }
+        let saved = snapshot(&canvas);
+        history.mark_saved();
+        draw(&mut history, &mut canvas, &pen, 0, 7);
+        for _ in 0..18 {
+            history.undo(&mut canvas);
         }
+        assert!(history.is_dirty());
+        assert_eq!(canvas.get_cell(0, 1, 3), &None);
+        for _ in 0..17 {
+            history.redo(&mut canvas);
+        }
+        assert!(!history.is_dirty());
+        assert_eq!(snapshot(&canvas), saved);
+        history.redo(&mut canvas);
+        assert!(history.is_dirty());
+    }
 
-        let canvas = excute_painting_command_to_canvas(
-            self.rendering_canvas.as_ref().unwrap(),
-            &self.edit_history[self.last_command * COMMAND_EXCUTE_GAP..],
+    #[test]
+    fn test_history_labels_and_jump() {
+        let mut canvas = Canvas::with_size(6, 4);
+        let pen = Pen::default();
+        let mut history = History::new();
+        draw(&mut history, &mut canvas, &pen, 5, 3);
+        let first = snapshot(&canvas);
+        history.begin_group();
+        for x in 0..3 {
+            draw(&mut history, &mut canvas, &pen, x, 0);
+        }
+        history.end_group();
+        history.push(
+            &mut canvas,
+            Command::RenameLayer {
+                layer: 0,
+                name: "ground".to_string(),
+            },
         );
+        history.undo(&mut canvas);
+        let branch = history.branch();
+        assert_eq!(
+            branch
+                .iter()
+                .map(|&step| history.label(step).clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Label::Start,
+                Label::Point {
+                    x: 5,
+                    y: 3,
+                    erase: false
+                },
+                Label::Stroke { cells: 3 },
+                Label::RenameLayer {
+                    name: "ground".to_string()
+                },
+            ]
+        );
+        assert_eq!(history.current(), branch[2]);
 
-        canvas
+        let current = snapshot(&canvas);
+        let preview = history.preview(&canvas, branch[1]);
+        assert_eq!(snapshot(&preview), first);
+        assert_eq!(history.current(), branch[2]);
+        assert_eq!(snapshot(&canvas), current);
+
+        history.jump_to(&mut canvas, branch[3]);
+        assert_eq!(canvas.layers[0].name, "ground");
+        history.jump_to(&mut canvas, branch[1]);
+        assert_eq!(snapshot(&canvas), first);
+        assert_eq!(history.branch(), branch);
     }
 
-    pub fn push(&mut self, command: Command) {
-        let last = self.edit_history.last();
-        if last == None || (command != Command::None && *last.unwrap() != command) {
-            self.edit_history.push(command);
-            self.clear_undo();
+    #[test]
+    fn test_history_branches() {
+        let mut canvas = Canvas::with_size(6, 4);
+        let pen = Pen::default();
+        let mut history = History::new();
+        assert!(!history.switch_branch(&mut canvas, true));
+        draw(&mut history, &mut canvas, &pen, 0, 0);
+        draw(&mut history, &mut canvas, &pen, 1, 0);
+        let old_branch = snapshot(&canvas);
+        history.undo(&mut canvas);
+        history.undo(&mut canvas);
+        draw(&mut history, &mut canvas, &pen, 2, 0);
+        let new_branch = snapshot(&canvas);
+        assert_eq!(history.branch_count(0), 2);
+        assert!(canvas.get_cell(0, 0, 0).is_none());
+
+        // the abandoned branch is still there, at the end it was left at
+        assert!(history.switch_branch(&mut canvas, false));
+        assert_eq!(snapshot(&canvas), old_branch);
+        history.undo(&mut canvas);
+        history.redo(&mut canvas);
+        assert_eq!(snapshot(&canvas), old_branch);
+        assert!(history.switch_branch(&mut canvas, true));
+        assert_eq!(snapshot(&canvas), new_branch);
+
+        // looking at the other branch does not change where redo goes
+        history.undo(&mut canvas);
+        let preview = history.preview(&canvas, 2);
+        assert_eq!(snapshot(&preview), old_branch);
+        history.redo(&mut canvas);
+        assert_eq!(snapshot(&canvas), new_branch);
+    }
+
+    #[test]
+    fn test_history_time_travel() {
+        let mut canvas = Canvas::with_size(6, 4);
+        let blank = snapshot(&canvas);
+        let pen = Pen::default();
+        let mut history = History::new();
+        let mut states = Vec::new();
+        for x in 0..3 {
+            draw(&mut history, &mut canvas, &pen, x, 0);
+            states.push(snapshot(&canvas));
+        }
+        history.undo(&mut canvas);
+        history.undo(&mut canvas);
+        draw(&mut history, &mut canvas, &pen, 5, 3);
+        let latest = snapshot(&canvas);
+        for (step, node) in history.nodes.iter_mut().enumerate() {
+            node.time = 100 + step as u64 * 10;
         }
+        assert_eq!(history.time_range(), (100, 140));
+
+        history.travel_to(&mut canvas, 125);
+        assert_eq!(snapshot(&canvas), states[1]);
+        history.travel_to(&mut canvas, 139);
+        assert_eq!(snapshot(&canvas), states[2]);
+        history.travel_to(&mut canvas, 99);
+        assert_eq!(snapshot(&canvas), blank);
+        history.travel_to(&mut canvas, 200);
+        assert_eq!(snapshot(&canvas), latest);
     }
 
-    pub fn clear(&mut self) {
-        self.last_command = 0;
-        self.edit_history.clear();
-        self.undo_history.clear();
-        self.rendering_canvas = None;
+    #[test]
+    fn test_history_layers_and_size() {
+        let mut canvas = Canvas::with_size(6, 4);
+        let pen = Pen {
+            tool: ToolEnum::Fill,
+            ..Default::default()
+        };
+        let mut history = History::new();
+        let mut states = vec![snapshot(&canvas)];
+        let commands = [
+            Command::AddLayer {
+                idx: 1,
+                name: "top".to_string(),
+            },
+            Command::new(2, 2, &pen, &None, false, &canvas),
+            Command::RenameLayer {
+                layer: 1,
+                name: "renamed".to_string(),
+            },
+            Command::SetLayerVisible {
+                layer: 0,
+                visible: false,
+            },
+            Command::MoveLayer { from: 0, to: 1 },
+            Command::MoveRegion {
+                layer: 1,
+                start_x: 0,
+                start_y: 0,
+                to_x: 2,
+                to_y: 1,
+                x: 3,
+                y: 2,
+            },
+            Command::ChangeCanvasSize {
+                width: 3,
+                height: 3,
+                start_x: 1,
+                start_y: 1,
+                to_x: 0,
+                to_y: 0,
+            },
+            Command::RemoveLayer { idx: 0 },
+        ];
+        for command in commands {
+            history.push(&mut canvas, command);
+            states.push(snapshot(&canvas));
+        }
+        assert_eq!(
+            (canvas.width, canvas.height, canvas.layers.len()),
+            (3, 3, 1)
+        );
+        for state in states.iter().rev().skip(1) {
+            history.undo(&mut canvas);
+            assert_eq!(&snapshot(&canvas), state);
+        }
+        for state in states.iter().skip(1) {
+            history.redo(&mut canvas);
+            assert_eq!(&snapshot(&canvas), state);
+        }
+
+        // previews go through the layer and size changes both ways
+        history.jump_to(&mut canvas, 3);
+        for (step, state) in states.iter().enumerate() {
+            assert_eq!(&snapshot(&history.preview(&canvas, step)), state);
+        }
+        assert_eq!(snapshot(&canvas), states[3]);
     }
 }
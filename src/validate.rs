@@ -0,0 +1,238 @@
+use std::fmt;
+
+use crate::animation::Animation;
+use crate::canvas::{default_layer_name, Canvas, Layer, MAX_LAYERS, MAX_SIZE};
+
+/// what is wrong with a layer of a loaded document
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    /// the canvas is wider or taller than [`MAX_SIZE`]
+    TooLarge { width: usize, height: usize },
+    /// the canvas has no layers or more than [`MAX_LAYERS`]
+    LayerCount { found: usize },
+    /// the layer does not hold `width * height` cells
+    CellCount { expected: usize, found: usize },
+    /// the cell uses a glyph the tileset does not have
+    GlyphOutOfRange { x: usize, y: usize, idx: usize },
+}
+
+/// a problem that would make editing the document panic, frames and layers count from 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub frame: usize,
+    pub layer: usize,
+    pub kind: ProblemKind,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frame {}, layer {}: ", self.frame + 1, self.layer + 1)?;
+        match self.kind {
+            ProblemKind::TooLarge { width, height } => write!(
+                f,
+                "{}x{} is larger than the largest canvas of {}x{}",
+                width, height, MAX_SIZE, MAX_SIZE
+            ),
+            ProblemKind::LayerCount { found } => {
+                write!(f, "{} layers where 1 to {} are allowed", found, MAX_LAYERS)
+            }
+            ProblemKind::CellCount { expected, found } => {
+                write!(f, "{} cells where {} are expected", found, expected)
+            }
+            ProblemKind::GlyphOutOfRange { x, y, idx } => {
+                write!(f, "glyph {} at ({}, {}) is not in the tileset", idx, x, y)
+            }
+        }
+    }
+}
+
+/// the number of cells of a `width` by `height` canvas, None when it is larger than [`MAX_SIZE`]
+pub fn canvas_size(width: usize, height: usize) -> Option<usize> {
+    (width <= MAX_SIZE && height <= MAX_SIZE).then(|| width * height)
+}
+
+/// checks that every layer fits its canvas and only uses the first `glyph_count` glyphs
+pub fn validate(animation: &Animation, glyph_count: usize) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (frame, canvas) in animation.frames.iter().map(|f| &f.canvas).enumerate() {
+        let Some(expected) = canvas_size(canvas.width, canvas.height) else {
+            problems.push(Problem {
+                frame,
+                layer: 0,
+                kind: ProblemKind::TooLarge {
+                    width: canvas.width,
+                    height: canvas.height,
+                },
+            });
+            continue;
+        };
+        let found = canvas.layers.len();
+        if found == 0 || found > MAX_LAYERS {
+            problems.push(Problem {
+                frame,
+                layer: 0,
+                kind: ProblemKind::LayerCount { found },
+            });
+        }
+        for (layer, cells) in canvas.layers.iter().map(|l| &l.cells).enumerate() {
+            if cells.len() != expected {
+                problems.push(Problem {
+                    frame,
+                    layer,
+                    kind: ProblemKind::CellCount {
+                        expected,
+                        found: cells.len(),
+                    },
+                });
+            }
+            for (i, cell) in cells.iter().enumerate().take(expected) {
+                if let Some(tile) = cell {
+                    if tile.idx >= glyph_count {
+                        problems.push(Problem {
+                            frame,
+                            layer,
+                            kind: ProblemKind::GlyphOutOfRange {
+                                x: i % canvas.width,
+                                y: i / canvas.width,
+                                idx: tile.idx,
+                            },
+                        });
+                    }
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// crops canvases to [`MAX_SIZE`], gives them between 1 and [`MAX_LAYERS`] layers, pads or truncates layers to their canvas and clamps glyphs to the last one of the tileset
+pub fn repair(animation: &mut Animation, glyph_count: usize) {
+    for canvas in animation.frames.iter_mut().map(|f| &mut f.canvas) {
+        repair_canvas(canvas, glyph_count);
+    }
+}
+
+/// [`repair`] for a single frame
+pub fn repair_canvas(canvas: &mut Canvas, glyph_count: usize) {
+    let last_glyph = glyph_count.saturating_sub(1);
+    let (width, height) = (canvas.width.min(MAX_SIZE), canvas.height.min(MAX_SIZE));
+    if (width, height) != (canvas.width, canvas.height) {
+        let old_width = canvas.width;
+        for layer in canvas.layers.iter_mut() {
+            let cells = &layer.cells;
+            layer.cells = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let i = y.checked_mul(old_width)?.checked_add(x)?;
+                    cells.get(i).copied().flatten()
+                })
+                .collect();
+        }
+        (canvas.width, canvas.height) = (width, height);
+    }
+    let size = width * height;
+    if canvas.layers.is_empty() {
+        canvas
+            .layers
+            .push(Layer::with_size(default_layer_name(0), size));
+    }
+    canvas.layers.truncate(MAX_LAYERS);
+    for layer in canvas.layers.iter_mut() {
+        layer.cells.resize(size, None);
+        for tile in layer.cells.iter_mut().flatten() {
+            tile.idx = tile.idx.min(last_glyph);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::canvas::TileState;
+    use crate::color::Color;
+
+    #[test]
+    fn test_validate_and_repair() -> Result<(), serde_json::error::Error> {
+        let mut animation: Animation = serde_json::from_str(
+            r#"{"width":2,"height":2,"cells":[null,{"idx":300,"fc":[255,255,255],"bc":[0,0,0]},null]}"#,
+        )?;
+        animation.frames.push(animation.frames[0].clone());
+        animation.frames[1].canvas = Canvas::with_size(1, 1);
+        let problems = validate(&animation, 256);
+        assert_eq!(
+            problems,
+            vec![
+                Problem {
+                    frame: 0,
+                    layer: 0,
+                    kind: ProblemKind::CellCount {
+                        expected: 4,
+                        found: 3
+                    },
+                },
+                Problem {
+                    frame: 0,
+                    layer: 0,
+                    kind: ProblemKind::GlyphOutOfRange {
+                        x: 1,
+                        y: 0,
+                        idx: 300
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            problems[1].to_string(),
+            "frame 1, layer 1: glyph 300 at (1, 0) is not in the tileset"
+        );
+
+        repair(&mut animation, 256);
+        assert!(validate(&animation, 256).is_empty());
+
+        let mut huge = Animation::from(Canvas::with_size(2, 1));
+        let canvas = &mut huge.frames[0].canvas;
+        *canvas.get_cell_mut(0, 1, 0) = Some(TileState {
+            idx: 1,
+            fc: Color::WHITE,
+            bc: Color::BLACK,
+        });
+        canvas.width = 1;
+        canvas.height = usize::MAX;
+        assert_eq!(
+            validate(&huge, 256)[0].kind,
+            ProblemKind::TooLarge {
+                width: 1,
+                height: usize::MAX
+            }
+        );
+        repair(&mut huge, 256);
+        let canvas = &huge.frames[0].canvas;
+        assert_eq!((canvas.width, canvas.height), (1, MAX_SIZE));
+        assert_eq!(canvas.get_cell(0, 0, 1).unwrap().idx, 1);
+        assert!(validate(&huge, 256).is_empty());
+
+        let mut layers = Animation::from(Canvas::with_size(2, 2));
+        layers.frames[0].canvas.layers.clear();
+        layers.frames.push(layers.frames[0].clone());
+        let extra = Canvas::with_size(2, 2).layers.remove(0);
+        layers.frames[1].canvas.layers = vec![extra; MAX_LAYERS + 1];
+        let problems = validate(&layers, 256);
+        assert_eq!(
+            problems.iter().map(|p| &p.kind).collect::<Vec<_>>(),
+            vec![
+                &ProblemKind::LayerCount { found: 0 },
+                &ProblemKind::LayerCount {
+                    found: MAX_LAYERS + 1
+                },
+            ]
+        );
+        repair(&mut layers, 256);
+        assert!(validate(&layers, 256).is_empty());
+        assert_eq!(layers.frames[0].canvas.layers[0].cells.len(), 4);
+        assert_eq!(layers.frames[1].canvas.layers.len(), MAX_LAYERS);
+        let canvas = &animation.frames[0].canvas;
+        assert_eq!(canvas.layers[0].cells.len(), 4);
+        assert_eq!(canvas.get_cell(0, 1, 0).unwrap().idx, 255);
+        Ok(())
+    }
+}